@@ -0,0 +1,118 @@
+//! Compares the `SpatialGrid` against checking every pair, with crowds of
+//! different sizes. Run with `cargo bench --bench spatial`.
+//!
+//! At 50 players and 200 bullets checking every pair is about twice as fast,
+//! and still a bit faster at 100 players and 400 bullets. The grid is three to
+//! four times as fast at 500 players and 2000 bullets. That's where
+//! `LINEAR_SCAN_LIMIT` comes from.
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+use tokyo::{
+    models::{BULLET_RADIUS, PLAYER_RADIUS},
+    spatial::{SpatialGrid, DEFAULT_CELL_SIZE},
+};
+
+const BOUNDS: (f32, f32) = (2880.0, 1920.0);
+// A busy meetup, and a crowd large enough for the grid to pay off
+const SMALL_CROWD: (usize, usize) = (50, 200);
+const MEDIUM_CROWD: (usize, usize) = (100, 400);
+const LARGE_CROWD: (usize, usize) = (500, 2000);
+
+// Each benchmark keeps running until it has taken at least this long
+const MEASUREMENT_TIME: Duration = Duration::from_secs(1);
+
+fn positions(count: usize, seed: u64) -> Vec<(f32, f32)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count).map(|_| (rng.gen_range(0.0, BOUNDS.0), rng.gen_range(0.0, BOUNDS.1))).collect()
+}
+
+fn is_colliding(a: (f32, f32), b: (f32, f32), radii: f32) -> bool {
+    let (d_x, d_y) = (b.0 - a.0, b.1 - a.1);
+    d_x * d_x + d_y * d_y < radii * radii
+}
+
+fn naive_collisions((player_count, bullet_count): (usize, usize)) -> Duration {
+    let players = positions(player_count, 1);
+    let bullets = positions(bullet_count, 2);
+
+    bench(|| {
+        let mut hits = 0;
+        for (i, player) in players.iter().enumerate() {
+            for other in &players[i + 1..] {
+                if is_colliding(*player, *other, PLAYER_RADIUS * 2.0) {
+                    hits += 1;
+                }
+            }
+        }
+        for bullet in &bullets {
+            for player in &players {
+                if is_colliding(*bullet, *player, BULLET_RADIUS + PLAYER_RADIUS) {
+                    hits += 1;
+                }
+            }
+        }
+        hits
+    })
+}
+
+fn grid_collisions((player_count, bullet_count): (usize, usize)) -> Duration {
+    let players = positions(player_count, 1);
+    let bullets = positions(bullet_count, 2);
+
+    // The grid and the query buffer are reused across ticks, like the server does.
+    let mut grid = SpatialGrid::new(BOUNDS, DEFAULT_CELL_SIZE);
+    let mut candidates = Vec::new();
+
+    bench(|| {
+        grid.clear();
+        for (i, player) in players.iter().enumerate() {
+            grid.insert(i, *player, *player, PLAYER_RADIUS);
+        }
+
+        let mut hits = 0;
+        for (i, player) in players.iter().enumerate() {
+            grid.query_into(*player, *player, PLAYER_RADIUS, &mut candidates);
+            for j in candidates.iter().cloned().filter(|j| *j > i) {
+                if is_colliding(*player, players[j], PLAYER_RADIUS * 2.0) {
+                    hits += 1;
+                }
+            }
+        }
+        for bullet in &bullets {
+            grid.query_into(*bullet, *bullet, BULLET_RADIUS, &mut candidates);
+            for j in candidates.iter().cloned() {
+                if is_colliding(*bullet, players[j], BULLET_RADIUS + PLAYER_RADIUS) {
+                    hits += 1;
+                }
+            }
+        }
+        hits
+    })
+}
+
+// Times `f` over enough iterations to fill MEASUREMENT_TIME, and returns how
+// long a single iteration took on average
+fn bench<T, F: FnMut() -> T>(mut f: F) -> Duration {
+    black_box(f());
+
+    let start = Instant::now();
+    let mut iterations = 0;
+    while start.elapsed() < MEASUREMENT_TIME {
+        black_box(f());
+        iterations += 1;
+    }
+
+    start.elapsed() / iterations
+}
+
+fn main() {
+    for (name, crowd) in &[("small", SMALL_CROWD), ("medium", MEDIUM_CROWD), ("large", LARGE_CROWD)]
+    {
+        let naive = naive_collisions(*crowd);
+        let grid = grid_collisions(*crowd);
+        println!("{:<6} crowd: naive {:>10?}  grid {:>10?}", name, naive, grid);
+    }
+}
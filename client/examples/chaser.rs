@@ -5,49 +5,11 @@
 /// the predefined behavior structs involve some more levels of abstraction,
 /// which you may or may not like. Please see the documentation in the `behavior`
 /// mod for more details.
+///
+/// The player, `Chaser`, is in `client/src/bots.rs` with the other players the
+/// server can host as bots.
 use rand::{thread_rng, Rng};
-use std::time::Instant;
-use tokyo::{
-    self,
-    analyzer::Analyzer,
-    behavior::{Behavior, Chase, FireAt, Sequence, Target},
-    models::*,
-    Handler,
-};
-
-#[derive(Default)]
-struct Player {
-    analyzer: Analyzer,
-    current_behavior: Box<Behavior>,
-}
-
-fn chase() -> Box<Behavior> {
-    // Behavior to keep chasing the target (in this case, the player with
-    // the highest score.) It yields to the next behavior when the distance
-    // to the player is less than 200.0.
-    let chase = Chase { target: Target::HighestScore, distance: 200.0 };
-
-    // Behavior to fire at the target player twice.
-    let fire = FireAt::with_times(Target::HighestScore, 2);
-
-    // A sequence of behaviors: chase and then fire.
-    Box::new(Sequence::with_slice(&[&chase, &fire]))
-}
-
-impl Handler for Player {
-    fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
-        self.analyzer.push_state(state, Instant::now());
-
-        if let Some(command) = self.current_behavior.next_command(&self.analyzer) {
-            Some(command)
-        } else {
-            // chase() returns a stateful Behavior, which we want to persist
-            // across ticks.
-            self.current_behavior = chase();
-            self.current_behavior.next_command(&self.analyzer)
-        }
-    }
-}
+use tokyo::{self, bots::Chaser};
 
 fn main() {
     let mut rng = thread_rng();
@@ -57,5 +19,5 @@ fn main() {
     let team_name = &format!("CHASER {}", rng.gen::<u8>());
 
     println!("starting up...");
-    tokyo::run(api_key, team_name, Player::default()).unwrap();
+    tokyo::run(api_key, team_name, Chaser::default()).unwrap();
 }
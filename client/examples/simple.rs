@@ -1,32 +1,11 @@
 /// A simple example client that only works with the bare minimal API. If you are
 /// new to Rust, or want to build your own logic from the ground up, this is a
 /// good start for you.
+///
+/// The player itself is `Simple` in `client/src/bots.rs`, which the server also
+/// hosts as a bot. Copy it into this file to start your own.
 use rand::{thread_rng, Rng};
-use tokyo::{self, models::*, Handler};
-
-#[derive(Default)]
-struct Player {
-    id: u32,
-    angle: f32,
-    counter: u32,
-}
-
-impl Handler for Player {
-    fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
-        self.id = state.id;
-
-        let angle = self.angle;
-        self.angle += 0.01;
-
-        self.counter += 1;
-
-        Some(match self.counter % 3 {
-            0 => GameCommand::Rotate(angle),
-            1 => GameCommand::Fire,
-            _ => GameCommand::Throttle(1.0),
-        })
-    }
-}
+use tokyo::{self, bots::Simple};
 
 fn main() {
     let mut rng = thread_rng();
@@ -36,5 +15,5 @@ fn main() {
     let team_name = &format!("H4CK TH3 PL4N3T {}", rng.gen::<u8>());
 
     println!("starting up...");
-    tokyo::run(api_key, team_name, Player::default()).unwrap();
+    tokyo::run(api_key, team_name, Simple::default()).unwrap();
 }
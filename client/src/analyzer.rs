@@ -1,7 +1,8 @@
 use crate::{
-    analyzer::{bullet::Bullet, player::Player},
+    analyzer::{bullet::Bullet, item::Item, player::Player},
     geom::*,
-    models::ClientState,
+    models::{ClientState, ItemKind, Obstacle, BULLET_RADIUS, PLAYER_RADIUS},
+    spatial::{SpatialGrid, DEFAULT_CELL_SIZE},
 };
 use std::{
     collections::HashMap,
@@ -9,6 +10,7 @@ use std::{
 };
 
 pub mod bullet;
+pub mod item;
 pub mod player;
 
 /// Collision detection etc is done at this compute interval.
@@ -20,6 +22,9 @@ pub const ANALYSIS_INTERVAL: Duration = Duration::from_millis(10);
 /// # Example
 ///
 /// ```
+/// # use std::time::Instant;
+/// # use tokyo::{analyzer::Analyzer, models::ClientState};
+/// # fn tick(state: &ClientState) {
 /// let mut analyzer = Analyzer::default();
 ///
 /// // Call push_state at each tick.
@@ -27,14 +32,20 @@ pub const ANALYSIS_INTERVAL: Duration = Duration::from_millis(10);
 ///
 /// // e.g. Find the closest player to yourself.
 /// if let Some(player) = analyzer.player_closest() {
-///     do_something_with(player);
+///     println!("Player {} is the closest", player.id);
 /// }
+/// # }
 /// ```
 #[derive(Debug)]
 pub struct Analyzer {
     own_player_id: u32,
     players: HashMap<u32, Player>,
     bullets: Vec<Bullet>,
+    obstacles: Vec<Obstacle>,
+    items: Vec<Item>,
+    // Indexes of player IDs and bullet indexes, for proximity queries
+    player_grid: SpatialGrid<u32>,
+    bullet_grid: SpatialGrid<usize>,
     last_update: Instant,
 }
 
@@ -44,6 +55,10 @@ impl Default for Analyzer {
             own_player_id: 0,
             players: HashMap::new(),
             bullets: Vec::new(),
+            obstacles: Vec::new(),
+            items: Vec::new(),
+            player_grid: SpatialGrid::new((0.0, 0.0), DEFAULT_CELL_SIZE),
+            bullet_grid: SpatialGrid::new((0.0, 0.0), DEFAULT_CELL_SIZE),
             last_update: Instant::now(),
         }
     }
@@ -56,17 +71,46 @@ impl Analyzer {
 
         let mut players = HashMap::new();
         for player_state in state.game_state.players.iter() {
-            let player = if let Some(mut prev_player) = self.players.remove(&player_state.id) {
-                prev_player.push_state(&player_state, &state.game_state.scoreboard, time);
+            let mut player = if let Some(mut prev_player) = self.players.remove(&player_state.id) {
+                prev_player.push_state(player_state, &state.game_state.scoreboard, time);
                 prev_player
             } else {
-                Player::with_state(&player_state, &state.game_state.scoreboard, time)
+                Player::with_state(player_state, &state.game_state.scoreboard, time)
             };
+            player.physics = state.game_state.physics;
             players.insert(player.id, player);
         }
         self.players = players;
 
-        self.bullets = state.game_state.bullets.iter().map(|state| Bullet::new(&state)).collect();
+        let bounds = state.game_state.bounds;
+        self.bullets = state
+            .game_state
+            .bullets
+            .iter()
+            .map(|state| Bullet::with_bounds(state, bounds))
+            .collect();
+
+        self.obstacles = state.game_state.obstacles.clone();
+
+        // The grids are reused, unless the arena changed
+        if self.player_grid.bounds() != bounds {
+            self.player_grid = SpatialGrid::new(bounds, DEFAULT_CELL_SIZE);
+            self.bullet_grid = SpatialGrid::new(bounds, DEFAULT_CELL_SIZE);
+        }
+
+        self.player_grid.clear();
+        for player in self.players.values() {
+            let position = (player.position.x, player.position.y);
+            self.player_grid.insert(player.id, position, position, 0.0);
+        }
+
+        self.bullet_grid.clear();
+        for (i, bullet) in self.bullets.iter().enumerate() {
+            let position = (bullet.position.x, bullet.position.y);
+            self.bullet_grid.insert(i, position, position, 0.0);
+        }
+
+        self.items = state.game_state.items.iter().map(Item::new).collect();
 
         self.last_update = time;
     }
@@ -91,7 +135,7 @@ impl Analyzer {
     /// Returns an `Iterator` of `Player`s, excluding your own.
     // FWIW, conservative_impl_trait will help get rid of Box.
     // https://github.com/rust-lang/rfcs/blob/master/text/1522-conservative-impl-trait.md
-    pub fn other_players<'a>(&'a self) -> impl Iterator<Item = &'a Player> {
+    pub fn other_players(&self) -> impl Iterator<Item = &Player> {
         self.players.values().filter(move |player| player.id != self.own_player_id)
     }
 
@@ -108,6 +152,12 @@ impl Analyzer {
             .min_by_key(|player| (player.trajectory.ave_abs_velocity().length() * 1e3) as u64)
     }
 
+    /// Returns a `Player`, who has the least health left. None if you are the
+    /// only `Player`.
+    pub fn player_lowest_health(&self) -> Option<&Player> {
+        self.other_players().min_by_key(|player| (player.health * 1e3) as u64)
+    }
+
     /// Returns a `Player`, who has earned the highest score so far. None if you
     /// are the highest scored one.
     pub fn player_highest_score(&self) -> Option<&Player> {
@@ -121,34 +171,104 @@ impl Analyzer {
         self.other_players().max_by_key(|player| player.score_history.project(duration))
     }
 
+    /// Returns an `Iterator` of `Player`s who are able to fire within the
+    /// `duration`, as far as their cooldown and reloading are concerned.
+    pub fn players_firing_within(&self, duration: Duration) -> impl Iterator<Item = &Player> {
+        self.other_players().filter(move |player| player.next_fire_in() <= duration)
+    }
+
     /// Returns an `Iterator` of `Player`s whose current location is within
     /// the `radius` of your own `Player`.
-    pub fn players_within<'a>(&'a self, radius: f32) -> impl Iterator<Item = &'a Player> {
-        self.other_players().filter(move |player| self.own_player().distance(*player) <= radius)
+    pub fn players_within(&self, radius: f32) -> impl Iterator<Item = &Player> {
+        let own_position = (self.own_player().position.x, self.own_player().position.y);
+
+        self.player_grid
+            .query(own_position, own_position, radius)
+            .into_iter()
+            .filter(move |id| *id != self.own_player_id)
+            .filter_map(move |id| self.players.get(&id))
+            .filter(move |player| self.own_player().distance(*player) <= radius)
     }
 
     /// Returns an `Iterator` of `Bullet`s that are shot by you and are still
     /// inside the arena. You can have at most 4 bullets at a time.
-    pub fn own_bullets<'a>(&'a self) -> impl Iterator<Item = &'a Bullet> {
+    pub fn own_bullets(&self) -> impl Iterator<Item = &Bullet> {
         self.bullets.iter().filter(move |bullet| bullet.player_id == self.own_player_id)
     }
 
     /// Returns an `Iterator` of `Bullet`s that are shot by other `Player`s and
     /// are still inside the arena.
-    pub fn other_bullets<'a>(&'a self) -> impl Iterator<Item = &'a Bullet> {
+    pub fn other_bullets(&self) -> impl Iterator<Item = &Bullet> {
         self.bullets.iter().filter(move |bullet| bullet.player_id != self.own_player_id)
     }
 
     /// Returns an `Iterator` of `Bullet`s that your `Player` would be colliding
-    /// within the `duration`, if you stayed at the current position.
-    pub fn bullets_colliding<'a>(&'a self, during: Duration) -> impl Iterator<Item = &'a Bullet> {
-        self.other_bullets()
-            .filter(move |bullet| self.own_player().is_colliding_during(bullet, during.clone()))
+    /// within the `duration`, if you stayed at the current position. `Bullet`s
+    /// that would be absorbed by an `Obstacle` on the way are not included.
+    pub fn bullets_colliding(&self, during: Duration) -> impl Iterator<Item = &Bullet> {
+        self.other_bullets().filter(move |bullet| {
+            self.own_player().is_colliding_during(bullet, during)
+                && self.has_line_of_sight(&bullet.position, &self.own_player().position)
+        })
     }
 
     /// Returns an `Iterator` of `Bullet`s that are shot by other `Player`s and
     /// are within the `radius` of your current position.
-    pub fn bullets_within<'a>(&'a self, radius: f32) -> impl Iterator<Item = &'a Bullet> {
-        self.other_bullets().filter(move |bullet| self.own_player().distance(*bullet) <= radius)
+    pub fn bullets_within(&self, radius: f32) -> impl Iterator<Item = &Bullet> {
+        let own_position = (self.own_player().position.x, self.own_player().position.y);
+
+        self.bullet_grid
+            .query(own_position, own_position, radius)
+            .into_iter()
+            .map(move |i| &self.bullets[i])
+            .filter(move |bullet| bullet.player_id != self.own_player_id)
+            .filter(move |bullet| self.own_player().distance(*bullet) <= radius)
+    }
+
+    /// Returns the `Item` specified by an ID.
+    pub fn item(&self, id: u32) -> Option<&Item> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    /// Returns an `Iterator` of the `Item`s lying in the arena.
+    pub fn items(&self) -> impl Iterator<Item = &Item> {
+        self.items.iter()
+    }
+
+    /// Returns an `Item`, which is closest to the current position of your own
+    /// `Player`. None if there are no items in the arena.
+    pub fn item_closest(&self) -> Option<&Item> {
+        self.items.iter().min_by_key(|item| (self.own_player().distance(*item) * 1e3) as u64)
+    }
+
+    /// Returns an `Item` of the given `kind`, which is closest to the current
+    /// position of your own `Player`. None if there are no such items.
+    pub fn item_closest_of_kind(&self, kind: ItemKind) -> Option<&Item> {
+        self.items
+            .iter()
+            .filter(|item| item.kind == kind)
+            .min_by_key(|item| (self.own_player().distance(*item) * 1e3) as u64)
+    }
+
+    /// Returns an `Iterator` of the `Obstacle`s placed in the arena.
+    pub fn obstacles(&self) -> impl Iterator<Item = &Obstacle> {
+        self.obstacles.iter()
+    }
+
+    /// Returns whether a bullet travelling from `from` to `to` would get there
+    /// without being absorbed by an `Obstacle`.
+    pub fn has_line_of_sight(&self, from: &Point, to: &Point) -> bool {
+        !self
+            .obstacles
+            .iter()
+            .any(|obstacle| obstacle.blocks_segment((from.x, from.y), (to.x, to.y), BULLET_RADIUS))
+    }
+
+    /// Returns whether a `Player` at the given `position` would be overlapping
+    /// an `Obstacle`, and therefore pushed out of it by the server.
+    pub fn is_blocked(&self, position: &Point) -> bool {
+        self.obstacles
+            .iter()
+            .any(|obstacle| obstacle.is_colliding(position.x, position.y, PLAYER_RADIUS))
     }
 }
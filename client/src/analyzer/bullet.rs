@@ -1,7 +1,9 @@
 use crate::{
     geom::*,
     models::{BulletState, BULLET_SPEED},
+    physics::{self, TICK_DT},
 };
+use std::time::Duration;
 
 /// `Bullet` struct contains the past and the current states of a single bullet
 /// identified by an ID. You will usually be accessing `Bullet`s through the
@@ -12,6 +14,13 @@ pub struct Bullet {
     pub position: Point,
     pub velocity: Vector,
     pub player_id: u32,
+    /// Seconds until the bullet disappears, None if it flies until it leaves
+    /// the arena.
+    pub remaining_life: Option<f32>,
+    pub bounces_left: u32,
+    /// The arena the bullet ricochets in, used by `project()` when it has
+    /// bounces left.
+    pub bounds: Option<(f32, f32)>,
 }
 
 impl Bullet {
@@ -22,13 +31,36 @@ impl Bullet {
             position: Point::new(state.x, state.y),
             velocity: Vector::with_angle(Radian::new(state.angle)) * BULLET_SPEED,
             player_id: state.player_id,
+            remaining_life: state.remaining_life,
+            bounces_left: state.bounces_left,
+            bounds: None,
         }
     }
 
+    /// Creates a new `Bullet` based on the given state, which ricochets inside
+    /// the given arena `bounds` when projected.
+    pub fn with_bounds(state: &BulletState, bounds: (f32, f32)) -> Self {
+        Bullet { bounds: Some(bounds), ..Bullet::new(state) }
+    }
+
+    /// Returns whether the `Bullet` will still be flying after `dt`, as far as
+    /// its lifetime is concerned.
+    pub fn is_alive_after(&self, dt: Duration) -> bool {
+        self.remaining_life.is_none_or(|life| life > dt.as_micros() as f32 / 1e6)
+    }
+
     /// Creates a virtual `Bullet` with `position` and `angle`, useful for
     /// collision simulation.
     pub fn with_position_angle(position: Point, angle: Radian) -> Self {
-        Bullet { id: 0, position, velocity: Vector::with_angle(angle) * BULLET_SPEED, player_id: 0 }
+        Bullet {
+            id: 0,
+            position,
+            velocity: Vector::with_angle(angle) * BULLET_SPEED,
+            player_id: 0,
+            remaining_life: None,
+            bounces_left: 0,
+            bounds: None,
+        }
     }
 }
 
@@ -48,4 +80,29 @@ impl VectorExt for Bullet {
     }
 }
 
-impl Moving for Bullet {}
+impl Moving for Bullet {
+    /// When the `Bullet` has bounces left, the projection ricochets off the
+    /// arena walls the same way the server does.
+    fn project(&self, dt: Duration) -> Point {
+        match self.bounds {
+            Some(bounds) if self.bounces_left > 0 => {
+                let mut state = BulletState {
+                    angle: self.velocity.angle_from_x_axis().get(),
+                    x: self.position.x,
+                    y: self.position.y,
+                    bounces_left: self.bounces_left,
+                    ..Default::default()
+                };
+
+                let mut remaining = dt.as_micros() as f32 / 1e6;
+                while remaining > 0.0 {
+                    physics::step_bullet(&mut state, bounds, remaining.min(TICK_DT));
+                    remaining -= TICK_DT;
+                }
+
+                Point::new(state.x, state.y)
+            },
+            _ => self.position.project_with_velocity(&self.velocity, dt),
+        }
+    }
+}
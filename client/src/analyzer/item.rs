@@ -0,0 +1,29 @@
+use crate::{
+    geom::*,
+    models::{ItemKind, ItemState},
+};
+
+/// `Item` struct represents a power-up lying in the arena, waiting to be picked
+/// up. You will usually be accessing `Item`s through the methods provided by
+/// `Analyzer`.
+#[derive(Debug)]
+pub struct Item {
+    pub id: u32,
+    pub kind: ItemKind,
+    pub position: Point,
+}
+
+impl Item {
+    /// Creates a new `Item` based on the given state.
+    pub fn new(state: &ItemState) -> Self {
+        Item { id: state.id, kind: state.kind, position: Point::new(state.x, state.y) }
+    }
+}
+
+/// `Item` struct provides some basic geometry operations through `PointExt`
+/// trait. See the `geom` mod.
+impl PointExt for Item {
+    fn point(&self) -> &Point {
+        &self.position
+    }
+}
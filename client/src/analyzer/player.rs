@@ -1,7 +1,11 @@
 use crate::{
     analyzer::{bullet::Bullet, ANALYSIS_INTERVAL},
     geom::*,
-    models::{PlayerState, BULLET_RADIUS, PLAYER_BASE_SPEED, PLAYER_MIN_THROTTLE, PLAYER_RADIUS},
+    models::{
+        ActiveEffect, ItemKind, PhysicsMode, PlayerState, PlayerStatus, BULLET_RADIUS,
+        PLAYER_MIN_THROTTLE, PLAYER_RADIUS,
+    },
+    physics::{self, TICK_DT},
 };
 use std::{
     collections::HashMap,
@@ -15,9 +19,25 @@ use std::{
 pub struct Player {
     pub id: u32,
     pub angle: Radian,
+    /// The heading the `Player` is turning towards. Same as `angle` unless the
+    /// server runs with `PhysicsMode::Momentum`.
+    pub target_angle: Radian,
     pub throttle: f32,
     pub position: Point,
     pub velocity: Vector,
+    pub health: f32,
+    pub last_damaged_by: Option<u32>,
+    pub effects: Vec<ActiveEffect>,
+    /// Shots left in the magazine, when the server limits them.
+    pub ammo: u32,
+    /// Seconds until the `Player` can fire again.
+    pub cooldown: f32,
+    /// Seconds of spawn protection left, the `Player` can't be hurt until then.
+    pub spawn_protection: f32,
+    /// Whether the `Player` is idle or disconnected.
+    pub status: PlayerStatus,
+    /// How the server moves the `Player`, used by `project()`.
+    pub physics: PhysicsMode,
     pub trajectory: Trajectory,
     pub score_history: ScoreHistory,
 }
@@ -27,10 +47,10 @@ impl Player {
     pub fn with_state(state: &PlayerState, scoreboard: &HashMap<u32, u32>, time: Instant) -> Self {
         let angle = Radian::new(state.angle);
         let position = Point::new(state.x, state.y);
-        let velocity = Vector::with_angle(angle) * state.throttle * PLAYER_BASE_SPEED;
+        let velocity = Vector::new(state.vel_x, state.vel_y);
 
         let mut trajectory = Trajectory::default();
-        trajectory.push(position.clone(), time);
+        trajectory.push(position, time);
 
         let mut score_history = ScoreHistory::default();
         score_history.push(*scoreboard.get(&state.id).unwrap_or(&0), time);
@@ -38,9 +58,18 @@ impl Player {
         Self {
             id: state.id,
             angle,
+            target_angle: Radian::new(state.target_angle),
             throttle: state.throttle,
             position,
             velocity,
+            health: state.health,
+            last_damaged_by: state.last_damaged_by,
+            effects: state.effects.clone(),
+            ammo: state.ammo,
+            cooldown: state.cooldown,
+            spawn_protection: state.spawn_protection,
+            status: state.status,
+            physics: PhysicsMode::default(),
             trajectory,
             score_history,
         }
@@ -56,10 +85,18 @@ impl Player {
         assert_eq!(self.id, state.id);
 
         self.angle = Radian::new(state.angle);
+        self.target_angle = Radian::new(state.target_angle);
         self.throttle = state.throttle;
         self.position = Point::new(state.x, state.y);
-        self.velocity = Vector::with_angle(self.angle) * state.throttle * PLAYER_BASE_SPEED;
-        self.trajectory.push(self.position.clone(), time);
+        self.velocity = Vector::new(state.vel_x, state.vel_y);
+        self.health = state.health;
+        self.last_damaged_by = state.last_damaged_by;
+        self.effects = state.effects.clone();
+        self.ammo = state.ammo;
+        self.cooldown = state.cooldown;
+        self.spawn_protection = state.spawn_protection;
+        self.status = state.status;
+        self.trajectory.push(self.position, time);
         self.score_history.push(*scoreboard.get(&state.id).unwrap_or(&0), time);
     }
 
@@ -68,19 +105,35 @@ impl Player {
         self.score_history.last_score()
     }
 
+    /// Returns how long until the `Player` is able to fire again. Zero if it
+    /// can fire right now, as far as the cooldown and reloading are concerned.
+    pub fn next_fire_in(&self) -> Duration {
+        Duration::from_micros((self.cooldown * 1e6) as u64)
+    }
+
+    /// Returns whether the `Player` just spawned and can't be hurt yet.
+    pub fn is_protected(&self) -> bool {
+        self.spawn_protection > 0.0
+    }
+
+    /// Returns whether the `Player` currently benefits from the given item.
+    pub fn has_effect(&self, kind: ItemKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
     /// Returns whether the `Player` will be colliding the given `Bullet` at a
     /// particular time in the future, specified by `interval`.
     pub fn is_colliding_at(&self, bullet: &Bullet, interval: Duration) -> bool {
-        self.project(interval).distance(&bullet.project(interval)) < BULLET_RADIUS + PLAYER_RADIUS
+        bullet.is_alive_after(interval)
+            && self.project(interval).distance(&bullet.project(interval))
+                < BULLET_RADIUS + PLAYER_RADIUS
     }
 
     /// Returns whether the `Player` will be colliding the given `Bullet` during
     /// the given `interval`.
     pub fn is_colliding_during(&self, bullet: &Bullet, interval: Duration) -> bool {
         let num_analysis = (interval.as_millis() / ANALYSIS_INTERVAL.as_millis()) as u32;
-        (1..=num_analysis)
-            .map(|tick| self.is_colliding_at(bullet, ANALYSIS_INTERVAL * tick))
-            .any(|hit| hit)
+        (1..=num_analysis).any(|tick| self.is_colliding_at(bullet, ANALYSIS_INTERVAL * tick))
     }
 }
 
@@ -89,9 +142,18 @@ impl Default for Player {
         Self {
             id: 0,
             angle: Radian::zero(),
+            target_angle: Radian::zero(),
             throttle: PLAYER_MIN_THROTTLE,
             position: Point::zero(),
             velocity: Vector::zero(),
+            health: 0.0,
+            last_damaged_by: None,
+            effects: Vec::new(),
+            ammo: 0,
+            cooldown: 0.0,
+            spawn_protection: 0.0,
+            status: PlayerStatus::Active,
+            physics: PhysicsMode::default(),
             trajectory: Trajectory::default(),
             score_history: ScoreHistory::default(),
         }
@@ -114,7 +176,35 @@ impl VectorExt for Player {
     }
 }
 
-impl Moving for Player {}
+impl Moving for Player {
+    /// With `PhysicsMode::Momentum`, the projection follows the same
+    /// acceleration, drag and turning as the server, assuming the `Player`
+    /// keeps the current throttle and target angle.
+    fn project(&self, dt: Duration) -> Point {
+        match self.physics {
+            PhysicsMode::Instant => self.position.project_with_velocity(&self.velocity, dt),
+            PhysicsMode::Momentum => {
+                let mut state = PlayerState::new(self.id);
+                state.angle = self.angle.get();
+                state.target_angle = self.target_angle.get();
+                state.throttle = self.throttle;
+                state.x = self.position.x;
+                state.y = self.position.y;
+                state.vel_x = self.velocity.x;
+                state.vel_y = self.velocity.y;
+                state.effects = self.effects.clone();
+
+                let mut remaining = dt.as_micros() as f32 / 1e6;
+                while remaining > 0.0 {
+                    physics::step(&mut state, PhysicsMode::Momentum, remaining.min(TICK_DT));
+                    remaining -= TICK_DT;
+                }
+
+                Point::new(state.x, state.y)
+            },
+        }
+    }
+}
 
 /// `Trajectory` contains the past positions of a `Player`. You may want to use
 /// it to infer the move behavior and logic of a `Player` of your interest.
@@ -136,7 +226,7 @@ impl Trajectory {
     /// It panics if the `push()` method has not been called before. It should
     /// not happen as long as you are calling `Analyzer::push_state()` at the
     /// beginning of each `tick()` method.
-    pub fn last_position<'a>(&'a self) -> &'a Point {
+    pub fn last_position(&self) -> &Point {
         &self.positions.last().unwrap().0
     }
 
@@ -1,9 +1,8 @@
 use crate::{
-    analyzer::{bullet::Bullet, player::Player, Analyzer},
+    analyzer::{bullet::Bullet, item::Item, player::Player, Analyzer},
     geom::*,
-    models::{GameCommand, PLAYER_MAX_THROTTLE, PLAYER_MIN_THROTTLE},
+    models::{GameCommand, ItemKind, PLAYER_MAX_THROTTLE},
 };
-use rand::{thread_rng, Rng};
 use std::{collections::VecDeque, fmt::Debug, time::Duration};
 
 /// `Behavior` trait abstracts an action or a series of actions that a `Player`
@@ -20,8 +19,15 @@ use std::{collections::VecDeque, fmt::Debug, time::Duration};
 /// A stateful usage of `Behavior`.
 ///
 /// ```
-/// impl Handlar for Player {
-///     fn tick(...) {
+/// # use std::time::Instant;
+/// # use tokyo::{analyzer::Analyzer, behavior::*, models::*, Handler};
+/// struct Player {
+///     analyzer: Analyzer,
+///     current_behavior: Sequence,
+/// }
+///
+/// impl Handler for Player {
+///     fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
 ///         self.analyzer.push_state(state, Instant::now());
 ///
 ///         if let Some(next_command) = self.current_behavior.next_command(&self.analyzer) {
@@ -33,9 +39,11 @@ use std::{collections::VecDeque, fmt::Debug, time::Duration};
 ///         // fired.
 ///         self.current_behavior = Self::next_behavior();
 ///
-///         self.current_behavior.next_command(&analyzer)
+///         self.current_behavior.next_command(&self.analyzer)
 ///     }
+/// }
 ///
+/// impl Player {
 ///     fn next_behavior() -> Sequence {
 ///         // Behavior to keep chasing the target (in this case, the player with
 ///         // the highest score.) It yields to the next behavior when the distance
@@ -54,8 +62,14 @@ use std::{collections::VecDeque, fmt::Debug, time::Duration};
 /// A stateless usage of `Behavior`.
 ///
 /// ```
-/// impl Handlar for Player {
-///     fn tick(...) {
+/// # use std::time::{Duration, Instant};
+/// # use tokyo::{analyzer::Analyzer, behavior::*, geom::*, models::*, Handler};
+/// struct Player {
+///     analyzer: Analyzer,
+/// }
+///
+/// impl Handler for Player {
+///     fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
 ///         self.analyzer.push_state(state, Instant::now());
 ///
 ///         // Find one of the bullets that are colliding within a second.
@@ -64,7 +78,7 @@ use std::{collections::VecDeque, fmt::Debug, time::Duration};
 ///
 ///             // Try to dodge from the bullet by moving to a direction roughly
 ///             // perpendicular to the bullet velocity.
-///             let dodge = Sequence::with_slice(&[
+///             let mut dodge = Sequence::with_slice(&[
 ///                 &Rotate::with_margin_degrees(angle, 30.0),
 ///                 &Throttle::max(),
 ///             ]);
@@ -89,16 +103,16 @@ pub trait Behavior: Send + Debug {
 
     // `Clone` does not work nicely with `Box` yet, so you'll need to implement
     // this method manually for each struct.
-    fn box_clone(&self) -> Box<Behavior>;
+    fn box_clone(&self) -> Box<dyn Behavior>;
 }
 
-impl Clone for Box<Behavior> {
+impl Clone for Box<dyn Behavior> {
     fn clone(&self) -> Self {
         self.box_clone()
     }
 }
 
-impl Default for Box<Behavior> {
+impl Default for Box<dyn Behavior> {
     fn default() -> Self {
         Box::new(Skip {})
     }
@@ -109,7 +123,7 @@ impl Default for Box<Behavior> {
 /// then it moves to the second `Behavior`, and so forth.
 #[derive(Clone, Debug)]
 pub struct Sequence {
-    inner: VecDeque<Box<Behavior>>,
+    inner: VecDeque<Box<dyn Behavior>>,
 }
 
 impl Behavior for Sequence {
@@ -123,18 +137,24 @@ impl Behavior for Sequence {
         None
     }
 
-    fn box_clone(&self) -> Box<Behavior> {
+    fn box_clone(&self) -> Box<dyn Behavior> {
         Box::new(self.clone())
     }
 }
 
+impl Default for Sequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Sequence {
     pub fn new() -> Self {
         Sequence::with_slice(&[])
     }
 
-    pub fn with_slice(behaviors: &[&Behavior]) -> Self {
-        Self { inner: behaviors.into_iter().map(|b| b.box_clone()).collect::<VecDeque<_>>() }
+    pub fn with_slice(behaviors: &[&dyn Behavior]) -> Self {
+        Self { inner: behaviors.iter().map(|b| b.box_clone()).collect::<VecDeque<_>>() }
     }
 }
 
@@ -147,7 +167,7 @@ impl Behavior for Skip {
         None
     }
 
-    fn box_clone(&self) -> Box<Behavior> {
+    fn box_clone(&self) -> Box<dyn Behavior> {
         Box::new(self.clone())
     }
 }
@@ -162,7 +182,7 @@ impl Behavior for Noop {
         Some(GameCommand::Rotate(analyzer.own_player().angle.positive().get()))
     }
 
-    fn box_clone(&self) -> Box<Behavior> {
+    fn box_clone(&self) -> Box<dyn Behavior> {
         Box::new(self.clone())
     }
 }
@@ -183,7 +203,7 @@ impl Behavior for Throttle {
         }
     }
 
-    fn box_clone(&self) -> Box<Behavior> {
+    fn box_clone(&self) -> Box<dyn Behavior> {
         Box::new(self.clone())
     }
 }
@@ -228,7 +248,7 @@ impl Behavior for MoveTo {
         .next_command(analyzer)
     }
 
-    fn box_clone(&self) -> Box<Behavior> {
+    fn box_clone(&self) -> Box<dyn Behavior> {
         Box::new(self.clone())
     }
 }
@@ -250,7 +270,7 @@ impl Behavior for Rotate {
         }
     }
 
-    fn box_clone(&self) -> Box<Behavior> {
+    fn box_clone(&self) -> Box<dyn Behavior> {
         Box::new(self.clone())
     }
 }
@@ -281,11 +301,17 @@ impl Behavior for Fire {
         }
     }
 
-    fn box_clone(&self) -> Box<Behavior> {
+    fn box_clone(&self) -> Box<dyn Behavior> {
         Box::new(self.clone())
     }
 }
 
+impl Default for Fire {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Fire {
     pub fn new() -> Self {
         Self::with_times(1)
@@ -320,13 +346,12 @@ impl Behavior for FireAt {
                 // Don't bother solving the math. Monte Carlo would do in this small world.
                 let corrected_angle = (-30..30)
                     .map(|da| angle + Radian::degrees(da as f32))
-                    .filter(|angle| {
+                    .find(|angle| {
                         target.is_colliding_during(
                             &Bullet::with_position_angle(own_player.position, *angle),
                             Duration::from_secs(5),
                         )
                     })
-                    .next()
                     .unwrap_or(angle);
                 self.next = Sequence::with_slice(&[
                     &Rotate::with_margin_degrees(corrected_angle, 5.0),
@@ -338,7 +363,7 @@ impl Behavior for FireAt {
         None
     }
 
-    fn box_clone(&self) -> Box<Behavior> {
+    fn box_clone(&self) -> Box<dyn Behavior> {
         Box::new(self.clone())
     }
 }
@@ -353,29 +378,6 @@ impl FireAt {
     }
 }
 
-/// A `Behavior` to send a random command.
-#[derive(Clone, Debug)]
-struct Random;
-
-impl Behavior for Random {
-    fn next_command(&mut self, _: &Analyzer) -> Option<GameCommand> {
-        let mut rng = thread_rng();
-        match rng.gen_range(0, 4) {
-            0 => None,
-            1 => Some(GameCommand::Rotate(rng.gen_range(0.0, 2.0 * std::f32::consts::PI))),
-            2 => {
-                Some(GameCommand::Throttle(rng.gen_range(PLAYER_MIN_THROTTLE, PLAYER_MAX_THROTTLE)))
-            },
-            3 => Some(GameCommand::Fire),
-            _ => unreachable!(),
-        }
-    }
-
-    fn box_clone(&self) -> Box<Behavior> {
-        Box::new(self.clone())
-    }
-}
-
 /// A `Behavior` to keep moving towards the specified `target` until it reaches
 /// within the `distance`.
 #[derive(Clone, Debug)]
@@ -402,7 +404,37 @@ impl Behavior for Chase {
         None
     }
 
-    fn box_clone(&self) -> Box<Behavior> {
+    fn box_clone(&self) -> Box<dyn Behavior> {
+        Box::new(self.clone())
+    }
+}
+
+/// A `Behavior` to keep moving towards the specified `target` item until it is
+/// picked up, by you or by someone else. It keeps yielding commands the whole
+/// way there, so anything after it in a `Sequence` waits until the item is
+/// gone.
+#[derive(Clone, Debug)]
+pub struct Collect {
+    pub target: ItemTarget,
+}
+
+impl Behavior for Collect {
+    fn next_command(&mut self, analyzer: &Analyzer) -> Option<GameCommand> {
+        let item = self.target.get(analyzer)?;
+        let angle = analyzer.own_player().angle_to(item);
+
+        // Once we're heading for the item at full throttle there's nothing to
+        // do but wait for it to be picked up. Yielding None would end the
+        // behavior early, so hold the turn with a Noop instead.
+        Sequence::with_slice(&[
+            &Rotate::with_margin_degrees(angle, 10.0),
+            &Throttle::max(),
+            &Noop {},
+        ])
+        .next_command(analyzer)
+    }
+
+    fn box_clone(&self) -> Box<dyn Behavior> {
         Box::new(self.clone())
     }
 }
@@ -423,7 +455,7 @@ impl Behavior for Dodge {
         }
     }
 
-    fn box_clone(&self) -> Box<Behavior> {
+    fn box_clone(&self) -> Box<dyn Behavior> {
         Box::new(self.clone())
     }
 }
@@ -447,6 +479,9 @@ pub enum Target {
 
     /// Player with the highest predicted score at a certain time in the future.
     HighestScoreAfter(Duration),
+
+    /// Player with the least health left.
+    LowestHealth,
 }
 
 impl Target {
@@ -459,6 +494,34 @@ impl Target {
             Target::LeastMoving => analyzer.player_least_moving(),
             Target::HighestScore => analyzer.player_highest_score(),
             Target::HighestScoreAfter(after) => analyzer.player_highest_score_after(*after),
+            Target::LowestHealth => analyzer.player_lowest_health(),
+        }
+    }
+}
+
+/// `ItemTarget enum` is used to specify an `Item` based on some predefined
+/// conditions, the same way `Target` does for `Player`s. `Collect` works with
+/// `ItemTarget` to route to power-ups.
+#[derive(Clone, Debug)]
+pub enum ItemTarget {
+    /// Item specified by an ID.
+    Id(u32),
+
+    /// Item currently closest to you.
+    Closest,
+
+    /// Item of a particular kind currently closest to you.
+    ClosestOfKind(ItemKind),
+}
+
+impl ItemTarget {
+    /// Returns a reference to an `Item` based on the condition. `None` if no
+    /// items match the condition.
+    pub fn get<'a>(&self, analyzer: &'a Analyzer) -> Option<&'a Item> {
+        match self {
+            ItemTarget::Id(id) => analyzer.item(*id),
+            ItemTarget::Closest => analyzer.item_closest(),
+            ItemTarget::ClosestOfKind(kind) => analyzer.item_closest_of_kind(*kind),
         }
     }
 }
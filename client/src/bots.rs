@@ -0,0 +1,141 @@
+//! Ready-made players, the same ones as in the `examples` folder. The server
+//! hosts them as bots, and they're a good place to start reading if you'd
+//! rather change something that works than start from scratch.
+
+use crate::{
+    analyzer::Analyzer,
+    behavior::{Behavior, Chase, FireAt, Sequence, Target},
+    geom::*,
+    models::*,
+    Handler,
+};
+use euclid::Angle;
+use std::time::{Duration, Instant};
+
+/// Only works with the bare minimal API: spins, fires and drives in a circle.
+#[derive(Default)]
+pub struct Simple {
+    angle: f32,
+    counter: u32,
+}
+
+impl Handler for Simple {
+    fn tick(&mut self, _state: &ClientState) -> Option<GameCommand> {
+        let angle = self.angle;
+        self.angle += 0.01;
+
+        self.counter += 1;
+
+        Some(match self.counter % 3 {
+            0 => GameCommand::Rotate(angle),
+            1 => GameCommand::Fire,
+            _ => GameCommand::Throttle(1.0),
+        })
+    }
+}
+
+/// Uses the `Analyzer` and `Behavior`s: chases the leader and fires at them
+/// twice, over and over.
+#[derive(Default)]
+pub struct Chaser {
+    analyzer: Analyzer,
+    current_behavior: Box<dyn Behavior>,
+}
+
+impl Chaser {
+    fn chase() -> Box<dyn Behavior> {
+        // Behavior to keep chasing the target (in this case, the player with
+        // the highest score.) It yields to the next behavior when the distance
+        // to the player is less than 200.0.
+        let chase = Chase { target: Target::HighestScore, distance: 200.0 };
+
+        // Behavior to fire at the target player twice.
+        let fire = FireAt::with_times(Target::HighestScore, 2);
+
+        // A sequence of behaviors: chase and then fire.
+        Box::new(Sequence::with_slice(&[&chase, &fire]))
+    }
+}
+
+impl Handler for Chaser {
+    fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
+        self.analyzer.push_state(state, Instant::now());
+
+        if let Some(command) = self.current_behavior.next_command(&self.analyzer) {
+            Some(command)
+        } else {
+            // chase() returns a stateful Behavior, which we want to persist
+            // across ticks.
+            self.current_behavior = Chaser::chase();
+            self.current_behavior.next_command(&self.analyzer)
+        }
+    }
+}
+
+const SPRAY_ANGLE: f32 = 15.0;
+
+enum JakebotState {
+    Spray(usize, Angle<f32>),
+    Dodging(usize),
+}
+
+/// Dodges bullets, and sprays at the closest player.
+#[derive(Default)]
+pub struct Jakebot {
+    analyzer: Analyzer,
+    state: Option<JakebotState>,
+}
+
+impl Jakebot {
+    fn approach_closest(&mut self) -> Option<GameCommand> {
+        let me = self.analyzer.own_player();
+        let closest = self.analyzer.player_closest()?;
+
+        if me.distance(closest) < 200.0 {
+            None
+        } else {
+            let angle = me.angle_to(closest);
+            if (me.angle - angle).abs() > Angle::degrees(1.0) {
+                Some(GameCommand::Rotate(angle.get()))
+            } else {
+                Some(GameCommand::Throttle(1.0))
+            }
+        }
+    }
+}
+
+impl Handler for Jakebot {
+    fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
+        self.analyzer.push_state(state, Instant::now());
+
+        let me = self.analyzer.own_player();
+        if let Some(JakebotState::Dodging(count)) = self.state {
+            self.state = if count > 0 { Some(JakebotState::Dodging(count - 1)) } else { None };
+            Some(GameCommand::Throttle(1.0))
+        } else if self.analyzer.bullets_colliding(Duration::from_millis(2000)).count() > 0 {
+            self.state = Some(JakebotState::Dodging(5));
+            Some(GameCommand::Rotate((me.angle + Angle::degrees(90.0)).get()))
+        } else if let Some(JakebotState::Spray(count, angle)) = self.state {
+            if (me.angle - angle).abs() > Angle::degrees(1.0) {
+                Some(GameCommand::Rotate(angle.get()))
+            } else {
+                self.state = if count > 1 {
+                    Some(JakebotState::Spray(count - 1, angle + Angle::degrees(SPRAY_ANGLE)))
+                } else {
+                    None
+                };
+                Some(GameCommand::Fire)
+            }
+        } else if let Some(closest) = self.analyzer.player_closest() {
+            if self.analyzer.own_bullets().count() < 1 {
+                let angle = me.angle_to(closest);
+                self.state = Some(JakebotState::Spray(3, angle - Angle::degrees(SPRAY_ANGLE)));
+                Some(GameCommand::Rotate(angle.get()))
+            } else {
+                self.approach_closest()
+            }
+        } else {
+            self.approach_closest()
+        }
+    }
+}
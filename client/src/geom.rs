@@ -26,33 +26,23 @@ pub type Vector = Vector2D<f32>;
 /// [`euclid` documentation]: https://docs.rs/euclid/0.19.6/euclid/struct.Angle.html
 pub type Radian = Angle<f32>;
 
-trait AsSecsF32 {
-    fn as_secs_f32(&self) -> f32;
-}
-
-impl AsSecsF32 for Duration {
-    fn as_secs_f32(&self) -> f32 {
-        self.as_nanos() as f32 / 1e9
-    }
-}
-
 /// Extension methods for the `Point` type alias.
 pub trait PointExt {
     fn point(&self) -> &Point;
 
     /// Returns the distance between this and the given points.
-    fn distance(&self, other: &PointExt) -> f32 {
+    fn distance(&self, other: &dyn PointExt) -> f32 {
         (*other.point() - *self.point()).length()
     }
 
     /// Returns the angle of the line connecting this point to the given point.
-    fn angle_to(&self, other: &PointExt) -> Radian {
+    fn angle_to(&self, other: &dyn PointExt) -> Radian {
         (*other.point() - *self.point()).angle_from_x_axis()
     }
 
     /// Returns the velocity at which one travels from this point to the given
     /// point for the amount of time `dt`.
-    fn velocity_to(&self, other: &PointExt, dt: Duration) -> Vector {
+    fn velocity_to(&self, other: &dyn PointExt, dt: Duration) -> Vector {
         (*other.point() - *self.point()) / dt.as_secs_f32()
     }
 
@@ -97,6 +87,7 @@ pub trait RadianExt {
     fn radian(&self) -> &Radian;
 
     /// Creates a new `Radian` based on a raw value in radians.
+    #[allow(clippy::new_ret_no_self)]
     fn new(radians: f32) -> Radian {
         Radian::radians(radians)
     }
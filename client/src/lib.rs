@@ -8,8 +8,11 @@ extern crate serde_derive;
 
 pub mod analyzer;
 pub mod behavior;
+pub mod bots;
 pub mod geom;
 pub mod models;
+pub mod physics;
+pub mod spatial;
 
 use crate::models::{ClientState, GameCommand, GameState, ServerToClient, MIN_COMMAND_INTERVAL};
 use failure::Error;
@@ -17,15 +20,33 @@ use futures::{Future, Sink, Stream};
 use std::{
     env,
     fmt::Debug,
+    mem,
     sync::{Arc, Mutex},
+    thread,
+    time::Duration,
 };
 use tokio_tungstenite as tokio_ws;
-use tokio_ws::tungstenite as ws;
+use tokio_ws::tungstenite::{
+    self as ws, handshake::client::Request, protocol::frame::coding::CloseCode,
+};
 use url::{
     percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET},
     Url,
 };
 
+// How long to wait before reconnecting. It doubles, up to the maximum, for
+// as long as the server can't be reached.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+// What the server told us before a connection ended, to know how to carry on
+#[derive(Debug, Default)]
+struct Reconnect {
+    redirect: Option<String>,     // The room the server moved us to.
+    session: Option<String>,      // The latest token to resume the same player with.
+    auth_failure: Option<String>, // Why the server turned us away, if it did.
+}
+
 /// `Handler` is provided as the trait that players can implement to interact
 /// with the game server.
 pub trait Handler {
@@ -36,7 +57,7 @@ pub trait Handler {
 }
 
 fn log_err<E: Debug>(e: E) {
-    eprintln!("{:?}", e)
+    log::warn!("{:?}", e)
 }
 
 fn is_player_alive(state: &ClientState) -> bool {
@@ -46,7 +67,7 @@ fn is_player_alive(state: &ClientState) -> bool {
 fn build_game_loop<H, S, D>(
     sink: S,
     client_state: Arc<Mutex<ClientState>>,
-    mut handler: H,
+    handler: Arc<Mutex<H>>,
 ) -> impl Future<Item = (), Error = ()>
 where
     H: Handler + Send + 'static,
@@ -57,12 +78,17 @@ where
     tokio::timer::Interval::new_interval(MIN_COMMAND_INTERVAL)
         // Give the user a chance to take a turn
         .filter_map(move |_| {
-            let client_state = &*client_state.lock().unwrap();
-            if is_player_alive(client_state) {
-                handler.tick(client_state)
+            let client_state = &mut *client_state.lock().unwrap();
+            let command = if is_player_alive(client_state) {
+                handler.lock().unwrap().tick(client_state)
             } else {
                 None
-            }
+            };
+
+            // Rejections and chat messages are only reported once.
+            client_state.rejected = None;
+            client_state.chat.clear();
+            command
         })
         // Convert their command to a websocket message
         .map(move |command: GameCommand| {
@@ -75,14 +101,16 @@ where
         .map(|_| ()) // throw away leftovers from forward
 }
 
-fn build_state_updater<S, D>(
+fn build_state_updater<S>(
     stream: S,
     client_state: Arc<Mutex<ClientState>>,
+    reconnect: Arc<Mutex<Reconnect>>,
 ) -> impl Future<Item = (), Error = ()>
 where
-    S: Stream<Item = ws::Message, Error = D>,
-    D: Debug,
+    S: Stream<Item = ws::Message, Error = ws::Error>,
 {
+    let close_reconnect = reconnect.clone();
+
     stream
         // We only care about text websocket messages.
         .filter_map(|message| message.into_text().ok())
@@ -97,47 +125,173 @@ where
                 ServerToClient::GameState(state) => {
                     (*client_state).lock().unwrap().game_state = state;
                 },
+                ServerToClient::Rejected(rejection) => {
+                    (*client_state).lock().unwrap().rejected = Some(rejection);
+                },
+                ServerToClient::SlowConnection(frames_behind) => {
+                    log::warn!(
+                        "The server is {} frames ahead of us, speed up or get disconnected",
+                        frames_behind
+                    );
+                },
+                ServerToClient::Chat(message) => {
+                    (*client_state).lock().unwrap().chat.push(message);
+                },
+                ServerToClient::Redirect(room) => {
+                    // The server closes the connection right after
+                    reconnect.lock().unwrap().redirect = Some(room);
+                },
+                ServerToClient::Session(token) => {
+                    reconnect.lock().unwrap().session = Some(token);
+                },
                 _ => {},
             }
 
             Ok(())
         })
-        .map_err(log_err)
+        .map_err(move |e| {
+            // The server closes with a policy violation when it turns us away.
+            if let ws::Error::ConnectionClosed(Some(frame)) = &e {
+                if frame.code == CloseCode::Policy {
+                    close_reconnect.lock().unwrap().auth_failure = Some(frame.reason.to_string());
+                }
+            }
+            log_err(e)
+        })
 }
 
 /// Begin the client-side game loop, using the provided struct that implements `Handler`
-/// to act on behalf of the player.
+/// to act on behalf of the player. Connection problems are reported through the `log`
+/// crate, set up a logger like `env_logger` to see them. Dropped connections are retried
+/// until the server turns the API key away, which is returned as an error.
 pub fn run<H>(key: &str, name: &str, handler: H) -> Result<(), Error>
 where
     H: Handler + Send + 'static,
 {
     let host = env::var("SERVER_HOST").unwrap_or("192.168.0.199".into());
-    let url = Url::parse(&format!(
-        "ws://{}/socket?key={}&name={}",
+    let mut url = Url::parse(&format!(
+        "ws://{}/socket?name={}",
         host,
-        key,
-        utf8_percent_encode(name, DEFAULT_ENCODE_SET).to_string()
+        utf8_percent_encode(name, DEFAULT_ENCODE_SET)
     ))?;
 
-    let client_state =
-        Arc::new(Mutex::new(ClientState { id: 0, game_state: GameState::default() }));
+    // Without a room, the server puts everybody in its main room
+    if let Ok(room) = env::var("SERVER_ROOM") {
+        url.query_pairs_mut().append_pair("room", &room);
+    }
 
-    let client = tokio_ws::connect_async(url)
-        .and_then(move |(websocket, _)| {
-            // Allow us to build two futures out of this connection - one for send, one for recv.
-            let (sink, stream) = websocket.split();
+    // With a queue like "ranked", the server finds a match and then
+    // redirects us to its room
+    if let Ok(queue) = env::var("SERVER_QUEUE") {
+        url.query_pairs_mut().append_pair("queue", &queue);
+    }
 
-            let game_loop = build_game_loop(sink, client_state.clone(), handler);
-            let state_updater = build_state_updater(stream, client_state);
+    let handler = Arc::new(Mutex::new(handler));
+    let mut delay = RECONNECT_DELAY;
 
-            // Return a future that will finish when either one of the two futures finish.
-            state_updater.select(game_loop).then(|_| Ok(()))
-        })
-        .map_err(log_err);
+    loop {
+        // The API key goes in a header so it doesn't end up in server logs
+        let mut request = Request::from(url.clone());
+        request.add_header("Authorization".into(), format!("Bearer {}", key).into());
+
+        let client_state = Arc::new(Mutex::new(ClientState {
+            id: 0,
+            game_state: GameState::default(),
+            rejected: None,
+            chat: vec![],
+        }));
+        let reconnect = Arc::new(Mutex::new(Reconnect::default()));
 
-    tokio::run(client);
-    Ok(())
+        let handler = handler.clone();
+        let reconnect_clone = reconnect.clone();
+        let handshake_reconnect = reconnect.clone();
+        let client = tokio_ws::connect_async(request)
+            .and_then(move |(websocket, _)| {
+                // Allow us to build two futures out of this connection - one for send, one for recv.
+                let (sink, stream) = websocket.split();
+
+                let game_loop = build_game_loop(sink, client_state.clone(), handler);
+                let state_updater = build_state_updater(stream, client_state, reconnect_clone);
+
+                // Return a future that will finish when either one of the two futures finish.
+                state_updater.select(game_loop).then(|_| Ok(()))
+            })
+            .map_err(move |e| {
+                // An API key the server doesn't know fails the handshake itself
+                if let ws::Error::Http(401) = e {
+                    handshake_reconnect.lock().unwrap().auth_failure =
+                        Some("Invalid API Key".to_string());
+                }
+                log_err(e)
+            });
+
+        tokio::run(client);
+
+        let reconnect = mem::take(&mut *reconnect.lock().unwrap());
+        if let Some(reason) = reconnect.auth_failure {
+            return Err(failure::err_msg(reason));
+        }
+        if let Some(room) = reconnect.redirect {
+            url = redirect_url(&url, &room);
+            continue;
+        }
+
+        // A dropped connection resumes the same player. Without a session, or
+        // if the server doesn't take the token anymore, it goes by the API key.
+        if let Some(token) = reconnect.session {
+            url = session_url(&url, &token);
+            delay = RECONNECT_DELAY;
+        }
+
+        log::warn!("Lost the connection to the server, reconnecting in {:?}", delay);
+        thread::sleep(delay);
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+// The same connection URL, but for the given room instead of a queue.
+// Sessions belong to a room, so the token is dropped too.
+fn redirect_url(url: &Url, room: &str) -> Url {
+    with_query_pair(url, &["room", "queue", "token"], ("room", room))
+}
+
+// The same connection URL, resuming the session with the given token
+fn session_url(url: &Url, token: &str) -> Url {
+    with_query_pair(url, &["token"], ("token", token))
+}
+
+fn with_query_pair(url: &Url, replaced: &[&str], (name, value): (&str, &str)) -> Url {
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| !replaced.contains(&name.as_ref()))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+
+    let mut url = url.clone();
+    url.query_pairs_mut().clear().extend_pairs(pairs).append_pair(name, value);
+    url
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    fn url(query: &str) -> Url {
+        Url::parse(&format!("ws://localhost/socket?{}", query)).unwrap()
+    }
+
+    #[test]
+    fn redirect_leaves_the_queue_and_the_session() {
+        let redirected = redirect_url(&url("name=bot&queue=ranked&token=abc"), "ranked-1");
+        assert_eq!(redirected, url("name=bot&room=ranked-1"));
+    }
+
+    #[test]
+    fn session_resumes_in_the_same_room() {
+        let resumed = session_url(&url("name=bot&room=main"), "abc");
+        assert_eq!(resumed, url("name=bot&room=main&token=abc"));
+
+        let resumed = session_url(&resumed, "def");
+        assert_eq!(resumed, url("name=bot&room=main&token=def"));
+    }
+}
@@ -11,6 +11,16 @@ pub const PLAYER_BASE_SPEED: f32 = 300.0;
 pub const PLAYER_MIN_THROTTLE: f32 = -1.0;
 pub const PLAYER_MAX_THROTTLE: f32 = 1.0;
 
+// Only used in `PhysicsMode::Momentum`. The top speed is still
+// `PLAYER_BASE_SPEED`, reached when the acceleration and the drag even out.
+pub const PLAYER_ACCELERATION: f32 = PLAYER_BASE_SPEED * PLAYER_DRAG; // in pixels-per-second²
+pub const PLAYER_DRAG: f32 = 2.0; // Fraction of the velocity lost per second.
+pub const PLAYER_MAX_TURN_RATE: f32 = std::f32::consts::PI; // in radians-per-second
+
+pub const ITEM_RADIUS: f32 = 12.0;
+pub const SPEED_BOOST_MULTIPLIER: f32 = 1.5;
+pub const SCORE_MULTIPLIER: u32 = 2;
+
 // Send commands more frequently than this interval, and consequences.
 pub const MIN_COMMAND_INTERVAL: Duration = Duration::from_millis(50);
 
@@ -18,13 +28,16 @@ pub const MIN_COMMAND_INTERVAL: Duration = Duration::from_millis(50);
 #[serde(tag = "e", content = "data")]
 pub enum GameCommand {
     #[serde(rename = "rotate")]
-    Rotate(f32), // In radians, no punish.
+    Rotate(f32), // In radians, no punish. With momentum, the heading to turn towards.
 
     #[serde(rename = "throttle")]
     Throttle(f32), // Between 0.0 and 1.0, otherwise consequences.
 
     #[serde(rename = "fire")]
     Fire, // Fire at the current angle.
+
+    #[serde(rename = "say")]
+    Say(String), // Say something to everybody in the room, rate limited.
 }
 
 #[derive(Debug, Serialize, Deserialize, Message)]
@@ -38,6 +51,54 @@ pub enum ServerToClient {
 
     #[serde(rename = "teamnames")]
     TeamNames(HashMap<u32, String>), // Send the game state to the client
+
+    #[serde(rename = "rejected")]
+    Rejected(Rejection), // Tell the client why their last command was ignored
+
+    #[serde(rename = "stats")]
+    Stats(HashMap<u32, PlayerStats>), // Send everyone's statistics, by player ID
+
+    #[serde(rename = "slow")]
+    SlowConnection(u32), // Warn the client it's this many frames behind, and about to be kicked
+
+    #[serde(rename = "session")]
+    Session(String), // Give the client a token to reconnect with, instead of its API key
+
+    #[serde(rename = "tournament")]
+    Tournament(Tournament), // Send spectators the bracket whenever it changes
+
+    #[serde(rename = "redirect")]
+    Redirect(String), // Move the client to this room, it has to reconnect there
+
+    #[serde(rename = "chat")]
+    Chat(ChatMessage), // Send everyone what a player said
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rejection {
+    #[serde(rename = "too_many_bullets")]
+    TooManyBullets, // Wait for one of your bullets to disappear.
+
+    #[serde(rename = "cooling_down")]
+    CoolingDown, // Wait for `PlayerState::cooldown` seconds.
+
+    #[serde(rename = "reloading")]
+    Reloading, // The magazine is empty, wait for `PlayerState::cooldown` seconds.
+
+    #[serde(rename = "chatting_too_fast")]
+    ChattingTooFast, // Wait a little before saying something else.
+
+    #[serde(rename = "message_too_long")]
+    MessageTooLong, // The server has a limit on how long chat messages can be.
+
+    #[serde(rename = "not_a_number")]
+    NotANumber, // Rotate and Throttle only take finite numbers.
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub player_id: u32,
+    pub text: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -47,21 +108,87 @@ pub struct PlayerState {
     pub throttle: f32,
     pub x: f32,
     pub y: f32,
+    #[serde(default)]
+    pub health: f32,
+    #[serde(default)]
+    pub last_damaged_by: Option<u32>, // Player ID of the last bullet that hit this player.
+    #[serde(default)]
+    pub effects: Vec<ActiveEffect>,
+    #[serde(default)]
+    pub target_angle: f32, // The last requested heading.
+    #[serde(default)]
+    pub vel_x: f32,
+    #[serde(default)]
+    pub vel_y: f32,
+    #[serde(default)]
+    pub ammo: u32, // Shots left in the magazine, when the server limits them.
+    #[serde(default)]
+    pub cooldown: f32, // Seconds until the player can fire again, including reloading.
+    #[serde(default)]
+    pub spawn_protection: f32, // Seconds of invulnerability left after (re)spawning.
+    #[serde(default)]
+    pub status: PlayerStatus,
 }
 
 impl PlayerState {
     pub fn new(id: u32) -> Self {
-        Self { id, angle: 0f32, throttle: 0f32, x: 0f32, y: 0f32 }
+        Self {
+            id,
+            angle: 0f32,
+            throttle: 0f32,
+            x: 0f32,
+            y: 0f32,
+            health: 0f32,
+            last_damaged_by: None,
+            effects: vec![],
+            target_angle: 0f32,
+            vel_x: 0f32,
+            vel_y: 0f32,
+            ammo: 0,
+            cooldown: 0f32,
+            spawn_protection: 0f32,
+            status: PlayerStatus::Active,
+        }
+    }
+
+    /// Returns whether the player currently benefits from the given item.
+    pub fn has_effect(&self, kind: ItemKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    /// Returns whether the player just spawned and can't be hurt yet.
+    pub fn is_protected(&self) -> bool {
+        self.spawn_protection > 0.0
     }
 
     pub fn randomize(&mut self, rng: &mut impl rand::Rng, (bound_right, bound_bottom): (f32, f32)) {
         self.angle = rng.gen_range(0.0, std::f32::consts::PI * 2.0);
+        self.target_angle = self.angle;
         self.throttle = 0.0;
+        self.vel_x = 0.0;
+        self.vel_y = 0.0;
         self.x = rng.gen_range(0.0, bound_right);
         self.y = rng.gen_range(0.0, bound_bottom);
     }
 }
 
+/// Whether a player is actually taking part in the game.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlayerStatus {
+    #[serde(rename = "active")]
+    #[default]
+    Active,
+
+    /// Hasn't moved or fired in a while, and might not earn survival points.
+    #[serde(rename = "idle")]
+    Idle,
+
+    /// Lost their connection, and will be removed from the game unless they
+    /// come back soon.
+    #[serde(rename = "disconnected")]
+    Disconnected,
+}
+
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct BulletState {
     pub id: u32,
@@ -69,6 +196,59 @@ pub struct BulletState {
     pub angle: f32,
     pub x: f32,
     pub y: f32,
+    #[serde(default)]
+    pub spawn_tick: u64, // `GameState::tick` when the bullet was fired.
+    #[serde(default)]
+    pub remaining_life: Option<f32>, // In seconds, None if it flies until it leaves the arena.
+    #[serde(default)]
+    pub bounces_left: u32, // How many more times it ricochets off the arena walls.
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ItemKind {
+    #[serde(rename = "speed")]
+    SpeedBoost, // Move `SPEED_BOOST_MULTIPLIER` times faster.
+
+    #[serde(rename = "rapidfire")]
+    RapidFire, // More bullets in flight at the same time.
+
+    #[serde(rename = "shield")]
+    Shield, // Bullets bounce off harmlessly.
+
+    #[serde(rename = "multiplier")]
+    ScoreMultiplier, // Earn `SCORE_MULTIPLIER` times the points.
+}
+
+/// A power-up lying in the arena, waiting to be picked up.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemState {
+    pub id: u32,
+    pub kind: ItemKind,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// A power-up a player has picked up, and the seconds it still lasts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ActiveEffect {
+    pub kind: ItemKind,
+    pub remaining: f32,
+}
+
+/// Running totals of how a player has been doing since they joined.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct PlayerStats {
+    pub kills: u32,
+    pub bullet_deaths: u32,
+    pub collision_deaths: u32,
+    pub shots_fired: u32,
+    pub shots_hit: u32,
+    pub accuracy: f32, // Fraction of the shots fired that hit another player.
+    pub distance_travelled: f32, // in pixels
+    pub longest_life: f32, // in seconds
+    pub kill_points: u32,
+    pub assist_points: u32,
+    pub survival_points: u32,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -77,13 +257,175 @@ pub struct DeadPlayer {
     pub player: PlayerState,
 }
 
+/// A static piece of arena geometry. Obstacles block players and absorb
+/// bullets. The same geometry routines are used by the server to resolve
+/// collisions and by the `Analyzer` to predict them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "shape")]
+pub enum Obstacle {
+    #[serde(rename = "circle")]
+    Circle { x: f32, y: f32, radius: f32 },
+
+    #[serde(rename = "polygon")]
+    Polygon { points: Vec<(f32, f32)> }, // Vertices in order, implicitly closed.
+}
+
+impl Obstacle {
+    /// Returns the point on the outline of the obstacle closest to `(x, y)`,
+    /// and whether `(x, y)` lies inside the obstacle.
+    pub fn closest_point(&self, x: f32, y: f32) -> ((f32, f32), bool) {
+        match self {
+            Obstacle::Circle { x: cx, y: cy, radius } => {
+                let (d_x, d_y) = (x - cx, y - cy);
+                let dist = (d_x * d_x + d_y * d_y).sqrt();
+
+                if dist > 0.0 {
+                    ((cx + d_x / dist * radius, cy + d_y / dist * radius), dist < *radius)
+                } else {
+                    ((cx + radius, *cy), true)
+                }
+            },
+            Obstacle::Polygon { points } => {
+                let closest = edges(points)
+                    .map(|(a, b)| closest_on_segment((x, y), a, b))
+                    .min_by(|p, q| squared_dist((x, y), *p).total_cmp(&squared_dist((x, y), *q)))
+                    .unwrap_or((x, y));
+
+                (closest, is_inside_polygon((x, y), points))
+            },
+        }
+    }
+
+    /// Returns whether a circle at `(x, y)` with the given `radius` overlaps
+    /// this obstacle.
+    pub fn is_colliding(&self, x: f32, y: f32, radius: f32) -> bool {
+        let (closest, inside) = self.closest_point(x, y);
+        inside || squared_dist((x, y), closest) < radius * radius
+    }
+
+    /// Returns the position a circle at `(x, y)` with the given `radius` has to
+    /// be moved to in order to stop overlapping this obstacle. None if it is
+    /// not overlapping.
+    pub fn push_out(&self, x: f32, y: f32, radius: f32) -> Option<(f32, f32)> {
+        let ((c_x, c_y), inside) = self.closest_point(x, y);
+        let (d_x, d_y) = (x - c_x, y - c_y);
+        let dist = (d_x * d_x + d_y * d_y).sqrt();
+
+        if !inside && dist >= radius {
+            return None;
+        }
+
+        // From the inside, the outline is in the opposite direction.
+        let sign = if inside { -1.0 } else { 1.0 };
+        let (n_x, n_y) = if dist > 0.0 { (d_x / dist, d_y / dist) } else { (0.0, 0.0) };
+
+        Some((c_x + sign * n_x * radius, c_y + sign * n_y * radius))
+    }
+
+    /// Returns whether a circle with the given `radius` moving in a straight
+    /// line from `from` to `to` would touch this obstacle on the way.
+    pub fn blocks_segment(&self, from: (f32, f32), to: (f32, f32), radius: f32) -> bool {
+        match self {
+            Obstacle::Circle { x, y, radius: obstacle_radius } => {
+                let closest = closest_on_segment((*x, *y), from, to);
+                let reach = obstacle_radius + radius;
+                squared_dist((*x, *y), closest) < reach * reach
+            },
+            Obstacle::Polygon { points } => {
+                if is_inside_polygon(from, points) || is_inside_polygon(to, points) {
+                    return true;
+                }
+
+                edges(points).any(|(a, b)| {
+                    segments_intersect(from, to, a, b)
+                        || [
+                            squared_dist(a, closest_on_segment(a, from, to)),
+                            squared_dist(b, closest_on_segment(b, from, to)),
+                            squared_dist(from, closest_on_segment(from, a, b)),
+                            squared_dist(to, closest_on_segment(to, a, b)),
+                        ]
+                        .iter()
+                        .any(|squared| *squared < radius * radius)
+                })
+            },
+        }
+    }
+}
+
+fn squared_dist(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (b.0 - a.0) + (b.1 - a.1) * (b.1 - a.1)
+}
+
+fn edges<'a>(points: &'a [(f32, f32)]) -> impl Iterator<Item = ((f32, f32), (f32, f32))> + 'a {
+    points.iter().cloned().zip(points.iter().cloned().cycle().skip(1))
+}
+
+fn closest_on_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    let (ab_x, ab_y) = (b.0 - a.0, b.1 - a.1);
+    let length_squared = ab_x * ab_x + ab_y * ab_y;
+
+    if length_squared == 0.0 {
+        return a;
+    }
+
+    let t = (((p.0 - a.0) * ab_x + (p.1 - a.1) * ab_y) / length_squared).clamp(0.0, 1.0);
+    (a.0 + ab_x * t, a.1 + ab_y * t)
+}
+
+fn segments_intersect(a: (f32, f32), b: (f32, f32), c: (f32, f32), d: (f32, f32)) -> bool {
+    let cross = |o: (f32, f32), p: (f32, f32), q: (f32, f32)| {
+        (p.0 - o.0) * (q.1 - o.1) - (p.1 - o.1) * (q.0 - o.0)
+    };
+
+    let d1 = cross(c, d, a);
+    let d2 = cross(c, d, b);
+    let d3 = cross(a, b, c);
+    let d4 = cross(a, b, d);
+
+    (d1 > 0.0) != (d2 > 0.0) && (d3 > 0.0) != (d4 > 0.0)
+}
+
+fn is_inside_polygon(p: (f32, f32), points: &[(f32, f32)]) -> bool {
+    // Even-odd ray casting towards +x.
+    edges(points)
+        .filter(|(a, b)| {
+            (a.1 > p.1) != (b.1 > p.1) && p.0 < a.0 + (p.1 - a.1) / (b.1 - a.1) * (b.0 - a.0)
+        })
+        .count()
+        % 2
+        == 1
+}
+
+/// How ships move. See the `physics` mod.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PhysicsMode {
+    /// Ships move at `throttle * PLAYER_BASE_SPEED` and snap to the requested
+    /// angle.
+    #[serde(rename = "instant")]
+    #[default]
+    Instant,
+
+    /// Ships accelerate, drift because of their momentum, and turn towards the
+    /// requested angle at a limited rate.
+    #[serde(rename = "momentum")]
+    Momentum,
+}
+
 #[derive(Clone, Default, Debug, Serialize, Deserialize, Message)]
 pub struct GameState {
+    #[serde(default)]
+    pub tick: u64,
     pub bounds: (f32, f32),
     pub players: Vec<PlayerState>,
     pub dead: Vec<DeadPlayer>,
     pub bullets: Vec<BulletState>,
     pub scoreboard: HashMap<u32, u32>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    #[serde(default)]
+    pub items: Vec<ItemState>,
+    #[serde(default)]
+    pub physics: PhysicsMode,
 }
 
 impl GameState {
@@ -96,4 +438,192 @@ impl GameState {
 pub struct ClientState {
     pub id: u32,
     pub game_state: GameState,
+    #[serde(default)]
+    pub rejected: Option<Rejection>, // Why a command got ignored since the last tick, if any.
+    #[serde(default)]
+    pub chat: Vec<ChatMessage>, // What players said since the last tick.
+}
+
+/// A tournament of short matches, each played in its own room. Players are
+/// identified by their team names.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tournament {
+    pub id: u32,
+    pub name: String,
+    pub format: TournamentFormat,
+    pub status: TournamentStatus,
+    pub round_secs: u64,                   // How long every match lasts.
+    pub entrants: Vec<String>,             // In seeding order.
+    pub rounds: Vec<Vec<TournamentMatch>>, // Elimination rounds are added as they're decided.
+    pub current_round: usize,
+    pub standings: Vec<Standing>, // Best first.
+    pub winner: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TournamentFormat {
+    /// Everybody plays everybody else once, one on one.
+    #[serde(rename = "round_robin")]
+    RoundRobin,
+
+    /// Only the winner of each match plays in the next round.
+    #[serde(rename = "elimination")]
+    Elimination,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TournamentStatus {
+    #[serde(rename = "running")]
+    Running,
+
+    #[serde(rename = "finished")]
+    Finished,
+
+    #[serde(rename = "cancelled")]
+    Cancelled,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TournamentMatch {
+    pub room: String,
+    pub players: Vec<String>,
+    pub status: MatchStatus,
+    pub ends: Option<SystemTime>,  // While it's being played.
+    pub results: Vec<MatchResult>, // Best first, once it's finished.
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchStatus {
+    #[serde(rename = "pending")]
+    Pending,
+
+    #[serde(rename = "playing")]
+    Playing,
+
+    #[serde(rename = "finished")]
+    Finished,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatchResult {
+    pub team_name: String,
+    pub points: u32,
+    pub kills: u32,
+    pub deaths: u32,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Standing {
+    pub team_name: String,
+    pub played: u32,
+    pub wins: u32, // Matches finished in first place.
+    pub points: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circle() -> Obstacle {
+        Obstacle::Circle { x: 100.0, y: 100.0, radius: 50.0 }
+    }
+
+    // A 100 by 100 square with its top left corner at (100, 100)
+    fn square() -> Obstacle {
+        Obstacle::Polygon {
+            points: vec![(100.0, 100.0), (200.0, 100.0), (200.0, 200.0), (100.0, 200.0)],
+        }
+    }
+
+    #[test]
+    fn closest_point_on_a_circle() {
+        assert_eq!(circle().closest_point(200.0, 100.0), ((150.0, 100.0), false));
+        assert_eq!(circle().closest_point(100.0, 80.0), ((100.0, 50.0), true));
+
+        // Any point on the outline will do from the center
+        let ((x, y), inside) = circle().closest_point(100.0, 100.0);
+        assert!(inside);
+        assert!(((x - 100.0).hypot(y - 100.0) - 50.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn closest_point_on_a_polygon() {
+        assert_eq!(square().closest_point(150.0, 50.0), ((150.0, 100.0), false));
+        assert_eq!(square().closest_point(250.0, 250.0), ((200.0, 200.0), false));
+        assert_eq!(square().closest_point(190.0, 150.0), ((200.0, 150.0), true));
+    }
+
+    #[test]
+    fn closest_point_on_a_polygon_survives_nan() {
+        let ((x, y), inside) = square().closest_point(f32::NAN, 150.0);
+        assert!(x.is_nan() || y.is_nan());
+        assert!(!inside);
+    }
+
+    #[test]
+    fn concave_polygons_have_an_outside_between_their_arms() {
+        // A U shape, open at the top
+        let u = Obstacle::Polygon {
+            points: vec![
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, 20.0),
+                (20.0, 20.0),
+                (20.0, 0.0),
+                (30.0, 0.0),
+                (30.0, 30.0),
+                (0.0, 30.0),
+            ],
+        };
+
+        assert!(u.is_colliding(5.0, 5.0, 0.0));
+        assert!(!u.is_colliding(15.0, 5.0, 1.0));
+        assert!(u.is_colliding(15.0, 5.0, 6.0));
+    }
+
+    #[test]
+    fn circles_collide_when_they_overlap() {
+        assert!(circle().is_colliding(100.0, 159.0, 10.0));
+        assert!(!circle().is_colliding(100.0, 161.0, 10.0));
+        assert!(square().is_colliding(95.0, 150.0, 10.0));
+        assert!(!square().is_colliding(85.0, 150.0, 10.0));
+        assert!(square().is_colliding(150.0, 150.0, 1.0));
+    }
+
+    #[test]
+    fn push_out_moves_circles_to_just_touch_the_outline() {
+        assert_eq!(circle().push_out(100.0, 200.0, 10.0), None);
+        assert_eq!(circle().push_out(100.0, 155.0, 10.0), Some((100.0, 160.0)));
+        assert_eq!(circle().push_out(100.0, 120.0, 10.0), Some((100.0, 160.0)));
+
+        assert_eq!(square().push_out(95.0, 150.0, 10.0), Some((90.0, 150.0)));
+        assert_eq!(square().push_out(110.0, 150.0, 10.0), Some((90.0, 150.0)));
+        assert_eq!(square().push_out(80.0, 150.0, 10.0), None);
+    }
+
+    #[test]
+    fn segments_through_an_obstacle_are_blocked() {
+        // Both ends are well clear, but the middle goes right through
+        assert!(circle().blocks_segment((0.0, 100.0), (300.0, 100.0), 2.0));
+        assert!(square().blocks_segment((0.0, 150.0), (300.0, 150.0), 2.0));
+
+        assert!(!circle().blocks_segment((0.0, 200.0), (300.0, 200.0), 2.0));
+        assert!(!square().blocks_segment((0.0, 250.0), (300.0, 250.0), 2.0));
+    }
+
+    #[test]
+    fn segments_grazing_an_obstacle_are_blocked_by_the_radius() {
+        assert!(circle().blocks_segment((0.0, 151.0), (300.0, 151.0), 2.0));
+        assert!(!circle().blocks_segment((0.0, 153.0), (300.0, 153.0), 2.0));
+
+        // Passing a corner diagonally
+        assert!(square().blocks_segment((190.0, 212.0), (212.0, 190.0), 2.0));
+        assert!(!square().blocks_segment((196.0, 210.0), (210.0, 196.0), 2.0));
+    }
+
+    #[test]
+    fn segments_inside_an_obstacle_are_blocked() {
+        assert!(square().blocks_segment((120.0, 120.0), (180.0, 180.0), 0.0));
+        assert!(circle().blocks_segment((100.0, 100.0), (100.0, 100.0), 0.0));
+    }
 }
@@ -0,0 +1,227 @@
+//! Movement rules. The server moves every ship with `step()` and every bullet
+//! with `step_bullet()` at each tick, and the `Analyzer` uses the same
+//! functions to project where things are going, so predictions match what the
+//! server will do.
+
+use crate::models::{
+    BulletState, ItemKind, PhysicsMode, PlayerState, BULLET_RADIUS, BULLET_SPEED,
+    PLAYER_ACCELERATION, PLAYER_BASE_SPEED, PLAYER_DRAG, PLAYER_MAX_TURN_RATE,
+    SPEED_BOOST_MULTIPLIER,
+};
+use std::f32::consts::PI;
+
+/// The server advances the game by this many seconds at each tick.
+pub const TICK_DT: f32 = 1.0 / 30.0;
+
+/// Advances the heading, velocity and position of a ship by `dt` seconds.
+/// Arena bounds and obstacles are not taken into account.
+pub fn step(player: &mut PlayerState, mode: PhysicsMode, dt: f32) {
+    let boost = if player.has_effect(ItemKind::SpeedBoost) { SPEED_BOOST_MULTIPLIER } else { 1.0 };
+
+    match mode {
+        PhysicsMode::Instant => {
+            player.angle = player.target_angle;
+
+            let (dir_x, dir_y) = (player.angle.cos(), player.angle.sin());
+            player.vel_x = dir_x * PLAYER_BASE_SPEED * boost * player.throttle;
+            player.vel_y = dir_y * PLAYER_BASE_SPEED * boost * player.throttle;
+        },
+        PhysicsMode::Momentum => {
+            // Turn towards the requested heading, the short way around.
+            let max_turn = PLAYER_MAX_TURN_RATE * dt;
+            let turn = wrap_angle(player.target_angle - player.angle).max(-max_turn).min(max_turn);
+            player.angle = wrap_angle(player.angle + turn);
+
+            // Thrust along the heading, while drag pulls the velocity down.
+            let (dir_x, dir_y) = (player.angle.cos(), player.angle.sin());
+            let thrust = PLAYER_ACCELERATION * boost * player.throttle * dt;
+            let drag = (1.0 - PLAYER_DRAG * dt).max(0.0);
+            player.vel_x = (player.vel_x + dir_x * thrust) * drag;
+            player.vel_y = (player.vel_y + dir_y * thrust) * drag;
+        },
+    }
+
+    player.x += player.vel_x * dt;
+    player.y += player.vel_y * dt;
+}
+
+/// Advances the position and the remaining life of a bullet by `dt` seconds,
+/// ricocheting off the arena walls while it has bounces left.
+pub fn step_bullet(bullet: &mut BulletState, (bound_right, bound_bottom): (f32, f32), dt: f32) {
+    bullet.x += bullet.angle.cos() * BULLET_SPEED * dt;
+    bullet.y += bullet.angle.sin() * BULLET_SPEED * dt;
+
+    if bullet.bounces_left > 0
+        && (bullet.x < BULLET_RADIUS || bullet.x > bound_right - BULLET_RADIUS)
+    {
+        let wall =
+            if bullet.x < BULLET_RADIUS { BULLET_RADIUS } else { bound_right - BULLET_RADIUS };
+        bullet.x = 2.0 * wall - bullet.x;
+        bullet.angle = wrap_angle(PI - bullet.angle);
+        bullet.bounces_left -= 1;
+    }
+
+    if bullet.bounces_left > 0
+        && (bullet.y < BULLET_RADIUS || bullet.y > bound_bottom - BULLET_RADIUS)
+    {
+        let wall =
+            if bullet.y < BULLET_RADIUS { BULLET_RADIUS } else { bound_bottom - BULLET_RADIUS };
+        bullet.y = 2.0 * wall - bullet.y;
+        bullet.angle = wrap_angle(-bullet.angle);
+        bullet.bounces_left -= 1;
+    }
+
+    if let Some(life) = &mut bullet.remaining_life {
+        *life -= dt;
+    }
+}
+
+/// Returns the same angle in the range of (-PI, PI].
+pub fn wrap_angle(angle: f32) -> f32 {
+    let wrapped = (angle + PI) % (2.0 * PI);
+    if wrapped <= 0.0 {
+        wrapped + PI
+    } else {
+        wrapped - PI
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ActiveEffect, PLAYER_DRAG};
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-3, "{} is not close to {}", actual, expected);
+    }
+
+    fn moving_player(throttle: f32, target_angle: f32) -> PlayerState {
+        PlayerState { throttle, target_angle, ..PlayerState::new(1) }
+    }
+
+    #[test]
+    fn wrap_angle_stays_within_one_turn() {
+        assert_close(wrap_angle(0.5), 0.5);
+        assert_close(wrap_angle(-0.5), -0.5);
+        assert_close(wrap_angle(0.5 + 2.0 * PI), 0.5);
+        assert_close(wrap_angle(-0.5 - 4.0 * PI), -0.5);
+        assert_close(wrap_angle(1.5 * PI), -0.5 * PI);
+        assert_close(wrap_angle(-1.5 * PI), 0.5 * PI);
+
+        // PI and -PI are the same heading, and only PI is in range
+        assert_close(wrap_angle(PI), PI);
+        assert_close(wrap_angle(-PI), PI);
+    }
+
+    #[test]
+    fn instant_ships_snap_to_their_heading_and_speed() {
+        let mut player = moving_player(0.5, PI / 2.0);
+        step(&mut player, PhysicsMode::Instant, 0.1);
+
+        assert_close(player.angle, PI / 2.0);
+        assert_close(player.vel_x, 0.0);
+        assert_close(player.vel_y, PLAYER_BASE_SPEED * 0.5);
+        assert_close(player.y, PLAYER_BASE_SPEED * 0.5 * 0.1);
+    }
+
+    #[test]
+    fn speed_boost_multiplies_the_speed() {
+        let mut player = moving_player(1.0, 0.0);
+        player.effects.push(ActiveEffect { kind: ItemKind::SpeedBoost, remaining: 1.0 });
+        step(&mut player, PhysicsMode::Instant, 1.0);
+
+        assert_close(player.vel_x, PLAYER_BASE_SPEED * SPEED_BOOST_MULTIPLIER);
+        assert_close(player.x, PLAYER_BASE_SPEED * SPEED_BOOST_MULTIPLIER);
+    }
+
+    #[test]
+    fn momentum_ships_turn_at_a_limited_rate_the_short_way_around() {
+        let mut player = moving_player(0.0, PI);
+        step(&mut player, PhysicsMode::Momentum, 0.1);
+        assert_close(player.angle, PLAYER_MAX_TURN_RATE * 0.1);
+
+        // Just below zero is closer clockwise than all the way around
+        let mut player = moving_player(0.0, 2.0 * PI - 0.1);
+        step(&mut player, PhysicsMode::Momentum, 1.0);
+        assert_close(player.angle, -0.1);
+    }
+
+    #[test]
+    fn momentum_ships_speed_up_to_about_the_base_speed() {
+        let mut player = moving_player(1.0, 0.0);
+        step(&mut player, PhysicsMode::Momentum, TICK_DT);
+        assert!(player.vel_x > 0.0 && player.vel_x < PLAYER_BASE_SPEED * 0.1);
+
+        for _ in 0..(10.0 / TICK_DT) as usize {
+            step(&mut player, PhysicsMode::Momentum, TICK_DT);
+        }
+
+        // Drag is applied after the thrust, so the top speed is a bit lower
+        let top_speed = PLAYER_BASE_SPEED * (1.0 - PLAYER_DRAG * TICK_DT);
+        assert_close(player.vel_x, top_speed);
+        assert_close(player.vel_y, 0.0);
+    }
+
+    #[test]
+    fn momentum_ships_drift_to_a_stop() {
+        let mut player = moving_player(0.0, 0.0);
+        player.vel_x = PLAYER_BASE_SPEED;
+
+        let mut last_x = player.x;
+        for _ in 0..(10.0 / TICK_DT) as usize {
+            step(&mut player, PhysicsMode::Momentum, TICK_DT);
+            assert!(player.x >= last_x);
+            last_x = player.x;
+        }
+
+        assert!(player.vel_x < 1.0);
+        assert!(player.x > 0.0);
+    }
+
+    fn bullet(x: f32, y: f32, angle: f32, bounces_left: u32) -> BulletState {
+        BulletState { x, y, angle, bounces_left, ..BulletState::default() }
+    }
+
+    #[test]
+    fn bullets_fly_in_a_straight_line() {
+        let mut b = BulletState { remaining_life: Some(1.0), ..bullet(100.0, 100.0, PI / 2.0, 0) };
+        step_bullet(&mut b, (1000.0, 1000.0), 0.1);
+
+        assert_close(b.x, 100.0);
+        assert_close(b.y, 100.0 + BULLET_SPEED * 0.1);
+        assert_close(b.remaining_life.unwrap(), 0.9);
+    }
+
+    #[test]
+    fn bullets_bounce_off_walls_while_they_have_bounces_left() {
+        let bounds = (1000.0, 1000.0);
+
+        // 60 pixels past the right wall comes back as 60 pixels short of it
+        let mut b = bullet(1000.0 - BULLET_RADIUS, 500.0, 0.0, 1);
+        step_bullet(&mut b, bounds, 60.0 / BULLET_SPEED);
+        assert_close(b.x, 1000.0 - BULLET_RADIUS - 60.0);
+        assert_close(b.angle, PI);
+        assert_eq!(b.bounces_left, 0);
+
+        let mut b = bullet(500.0, BULLET_RADIUS, -PI / 2.0, 2);
+        step_bullet(&mut b, bounds, 60.0 / BULLET_SPEED);
+        assert_close(b.y, BULLET_RADIUS + 60.0);
+        assert_close(b.angle, PI / 2.0);
+        assert_eq!(b.bounces_left, 1);
+
+        // Out of bounces, the bullet leaves the arena
+        let mut b = bullet(1000.0 - BULLET_RADIUS, 500.0, 0.0, 0);
+        step_bullet(&mut b, bounds, 60.0 / BULLET_SPEED);
+        assert!(b.x > bounds.0);
+    }
+
+    #[test]
+    fn bullets_bounce_out_of_corners() {
+        let mut b = bullet(990.0, 990.0, PI / 4.0, 2);
+        step_bullet(&mut b, (1000.0, 1000.0), 30.0 / BULLET_SPEED);
+
+        assert!(b.x < 1000.0 - BULLET_RADIUS && b.y < 1000.0 - BULLET_RADIUS);
+        assert_close(b.angle, -3.0 * PI / 4.0);
+        assert_eq!(b.bounces_left, 0);
+    }
+}
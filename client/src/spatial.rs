@@ -0,0 +1,188 @@
+//! A uniform grid to find things that are close to each other without looking
+//! at every pair. The server uses it for collisions, and the `Analyzer` for
+//! proximity queries.
+
+/// Cell size that works well for ships and bullets moving at most a few dozen
+/// pixels per tick.
+pub const DEFAULT_CELL_SIZE: f32 = 128.0;
+
+/// Up to this many things, checking every pair is as fast or faster than
+/// filling and querying a grid, see `benches/spatial.rs`. Below it, callers
+/// should skip the grid.
+pub const LINEAR_SCAN_LIMIT: usize = 128;
+
+/// `SpatialGrid` buckets values by the cells their bounding box covers. A value
+/// is either a point or a circle swept along a segment, so a moving thing can
+/// be looked up anywhere along its path during a tick. Anything outside of the
+/// `bounds` given at creation lands in the cells along the edges.
+#[derive(Debug)]
+pub struct SpatialGrid<T> {
+    bounds: (f32, f32),
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<T>>,
+}
+
+impl<T: Copy + Ord> SpatialGrid<T> {
+    pub fn new(bounds: (f32, f32), cell_size: f32) -> Self {
+        let columns = ((bounds.0 / cell_size).ceil() as usize).max(1);
+        let rows = ((bounds.1 / cell_size).ceil() as usize).max(1);
+
+        Self { bounds, cell_size, columns, rows, cells: vec![Vec::new(); columns * rows] }
+    }
+
+    /// The bounds the grid was created with.
+    pub fn bounds(&self) -> (f32, f32) {
+        self.bounds
+    }
+
+    /// Removes all the values, keeping the allocated cells around for reuse.
+    pub fn clear(&mut self) {
+        for cell in &mut self.cells {
+            cell.clear();
+        }
+    }
+
+    /// Adds a `value` for a circle of `radius` moving from `from` to `to`. Use
+    /// the same point twice for something standing still.
+    pub fn insert(&mut self, value: T, from: (f32, f32), to: (f32, f32), radius: f32) {
+        let (columns, rows) = self.covering(from, to, radius);
+
+        for row in rows {
+            for column in columns.clone() {
+                self.cells[row * self.columns + column].push(value);
+            }
+        }
+    }
+
+    /// Returns the values whose cells overlap the bounding box of a circle of
+    /// `radius` moving from `from` to `to`, sorted and without duplicates. These
+    /// are candidates only, the caller still needs to do the exact test.
+    pub fn query(&self, from: (f32, f32), to: (f32, f32), radius: f32) -> Vec<T> {
+        let mut values = Vec::new();
+        self.query_into(from, to, radius, &mut values);
+        values
+    }
+
+    /// Same as `query()`, but reuses the given `values` buffer.
+    pub fn query_into(&self, from: (f32, f32), to: (f32, f32), radius: f32, values: &mut Vec<T>) {
+        let (columns, rows) = self.covering(from, to, radius);
+
+        values.clear();
+        for row in rows {
+            for column in columns.clone() {
+                values.extend_from_slice(&self.cells[row * self.columns + column]);
+            }
+        }
+
+        if values.len() > 1 {
+            values.sort_unstable();
+            values.dedup();
+        }
+    }
+
+    fn covering(
+        &self,
+        from: (f32, f32),
+        to: (f32, f32),
+        radius: f32,
+    ) -> (std::ops::RangeInclusive<usize>, std::ops::RangeInclusive<usize>) {
+        let cell = |coord: f32, count: usize| {
+            ((coord / self.cell_size).floor().max(0.0) as usize).min(count - 1)
+        };
+
+        let columns = cell(from.0.min(to.0) - radius, self.columns)
+            ..=cell(from.0.max(to.0) + radius, self.columns);
+        let rows =
+            cell(from.1.min(to.1) - radius, self.rows)..=cell(from.1.max(to.1) + radius, self.rows);
+
+        (columns, rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> SpatialGrid<u32> {
+        SpatialGrid::new((1000.0, 1000.0), 100.0)
+    }
+
+    fn at(grid: &SpatialGrid<u32>, point: (f32, f32), radius: f32) -> Vec<u32> {
+        grid.query(point, point, radius)
+    }
+
+    #[test]
+    fn finds_values_in_the_same_cell_only() {
+        let mut grid = grid();
+        grid.insert(1, (150.0, 150.0), (150.0, 150.0), 0.0);
+        grid.insert(2, (450.0, 150.0), (450.0, 150.0), 0.0);
+
+        assert_eq!(at(&grid, (120.0, 180.0), 0.0), vec![1]);
+        assert_eq!(at(&grid, (450.0, 450.0), 0.0), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn results_are_sorted_without_duplicates() {
+        let mut grid = grid();
+        grid.insert(2, (150.0, 150.0), (150.0, 150.0), 0.0);
+        grid.insert(1, (150.0, 150.0), (150.0, 150.0), 0.0);
+        grid.insert(2, (250.0, 150.0), (250.0, 150.0), 0.0);
+
+        assert_eq!(grid.query((150.0, 150.0), (250.0, 150.0), 0.0), vec![1, 2]);
+    }
+
+    #[test]
+    fn bodies_spanning_cell_edges_are_found_from_both_sides() {
+        let mut grid = grid();
+        grid.insert(1, (195.0, 150.0), (195.0, 150.0), 10.0);
+
+        assert_eq!(at(&grid, (150.0, 150.0), 0.0), vec![1]);
+        assert_eq!(at(&grid, (250.0, 150.0), 0.0), vec![1]);
+        assert_eq!(at(&grid, (350.0, 150.0), 0.0), Vec::<u32>::new());
+
+        // The radius of the query reaches across edges too
+        assert_eq!(at(&grid, (305.0, 150.0), 10.0), vec![1]);
+    }
+
+    #[test]
+    fn moving_bodies_are_found_along_their_path() {
+        let mut grid = grid();
+        grid.insert(1, (50.0, 50.0), (450.0, 50.0), 0.0);
+
+        assert_eq!(at(&grid, (250.0, 50.0), 0.0), vec![1]);
+        assert_eq!(grid.query((250.0, 450.0), (250.0, 0.0), 0.0), vec![1]);
+    }
+
+    #[test]
+    fn positions_outside_the_bounds_land_on_the_edges() {
+        let mut grid = grid();
+        grid.insert(1, (-500.0, 50.0), (-500.0, 50.0), 0.0);
+        grid.insert(2, (5000.0, 5000.0), (5000.0, 5000.0), 0.0);
+
+        assert_eq!(at(&grid, (10.0, 50.0), 0.0), vec![1]);
+        assert_eq!(at(&grid, (-10.0, 50.0), 0.0), vec![1]);
+        assert_eq!(at(&grid, (990.0, 990.0), 0.0), vec![2]);
+        assert_eq!(at(&grid, (f32::MAX, f32::MAX), 0.0), vec![2]);
+    }
+
+    #[test]
+    fn query_into_replaces_the_buffer() {
+        let mut grid = grid();
+        grid.insert(1, (150.0, 150.0), (150.0, 150.0), 0.0);
+
+        let mut values = vec![7, 8, 9];
+        grid.query_into((150.0, 150.0), (150.0, 150.0), 0.0, &mut values);
+        assert_eq!(values, vec![1]);
+    }
+
+    #[test]
+    fn clear_removes_everything() {
+        let mut grid = grid();
+        grid.insert(1, (150.0, 150.0), (150.0, 150.0), 0.0);
+        grid.clear();
+
+        assert_eq!(at(&grid, (150.0, 150.0), 0.0), Vec::<u32>::new());
+    }
+}
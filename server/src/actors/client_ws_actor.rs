@@ -1,30 +1,155 @@
 use crate::{
-    actors::GameActor,
-    models::messages::{ClientStop, PlayerGameCommand},
+    actors::{game_actor::SocketEvent, lobby_actor::QueueEvent, GameActor, LobbyActor},
+    metrics::METRICS,
+    models::messages::{
+        AuthFailed, AuthMessage, Authenticated, ClientStop, Credentials, FramesBehind, Heartbeat,
+        PlayerGameCommand, Queued, Redirect, ServerRestarting, StateFrame,
+    },
     AppState,
 };
-use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, StreamHandler};
+use actix::{Actor, ActorContext, ActorFuture, Addr, AsyncContext, Handler, StreamHandler};
 use actix_web::ws::{self, CloseCode, CloseReason};
 use ratelimit_meter::{DirectRateLimiter, GCRA};
+use std::{
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
 use tokyo::models::ServerToClient;
 
 const ACTIONS_PER_SECOND: u32 = 22;
 
+// How many state frames a socket can fall behind before it gets warned, and
+// then disconnected
+const SLOW_CLIENT_WARNING_FRAMES: u32 = 30;
+const SLOW_CLIENT_MAX_FRAMES: u32 = 150;
+
+// How often to ping clients, so the game actor can tell live connections from
+// dead ones
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Where a socket's player goes once its credentials are accepted.
+#[derive(Debug)]
+pub enum Destination {
+    Room(Addr<GameActor>),
+    // The ranked queue, which redirects the client to a room once it finds a
+    // match
+    Queue(Addr<LobbyActor>),
+}
+
 #[derive(Debug)]
 pub struct ClientWsActor {
-    game_addr: Addr<GameActor>,
+    destination: Destination,
+    // None when the client sends its API key in the first message. The API
+    // key stays empty until the game actor or the lobby accepts the
+    // credentials.
+    credentials: Option<Credentials>,
     api_key: String,
     team_name: String,
     rate_limiter: DirectRateLimiter<GCRA>,
+    // Whether the socket is still writing out the last state frame, and the
+    // newest frame waiting for it to finish
+    sending_frame: bool,
+    pending_frame: Option<StateFrame>,
+    frames_behind: u32,
 }
 
 impl ClientWsActor {
-    pub fn new(game_addr: Addr<GameActor>, api_key: String, team_name: String) -> ClientWsActor {
-        let rate_limiter = DirectRateLimiter::<GCRA>::per_second(
-            std::num::NonZeroU32::new(ACTIONS_PER_SECOND).unwrap(),
-        );
+    pub fn new(
+        destination: Destination,
+        credentials: Option<Credentials>,
+        team_name: String,
+    ) -> ClientWsActor {
+        let rate_limiter =
+            DirectRateLimiter::<GCRA>::per_second(NonZeroU32::new(ACTIONS_PER_SECOND).unwrap());
+
+        let api_key = match credentials {
+            Some(Credentials::Spectator) => "SPECTATOR".to_string(),
+            _ => String::new(),
+        };
+
+        ClientWsActor {
+            destination,
+            credentials,
+            api_key,
+            team_name,
+            rate_limiter,
+            sending_frame: false,
+            pending_frame: None,
+            frames_behind: 0,
+        }
+    }
+
+    fn send_frame(&mut self, frame: StateFrame, ctx: &mut <Self as Actor>::Context) {
+        self.sending_frame = true;
+        ctx.text(frame.json);
+        METRICS.record_message_out();
+
+        // Count the frames skipped while this one was being written, a socket
+        // which is too slow for a single frame gets disconnected
+        let drained = ctx.drain().map(|_, act, ctx| {
+            act.sending_frame = false;
+            act.set_frames_behind(0);
 
-        ClientWsActor { game_addr, api_key, team_name, rate_limiter }
+            if let Some(frame) = act.pending_frame.take() {
+                act.send_frame(frame, ctx);
+            }
+        });
+        ctx.spawn(drained);
+    }
+
+    fn join(&mut self, credentials: Credentials, ctx: &mut <Self as Actor>::Context) {
+        let team_name = self.team_name.clone();
+
+        match &self.destination {
+            Destination::Room(game_addr) => {
+                game_addr.do_send(SocketEvent::Join(credentials, team_name, ctx.address()))
+            },
+            Destination::Queue(lobby_addr) => {
+                lobby_addr.do_send(QueueEvent::Join(credentials, team_name, ctx.address()))
+            },
+        }
+    }
+
+    // Expects the API key as the first message, if it didn't come with the
+    // request. Everything else is ignored until the game actor accepts it.
+    fn authenticate(&mut self, text: &str, ctx: &mut <Self as Actor>::Context) {
+        if self.credentials.is_some() {
+            return;
+        }
+
+        let key = serde_json::from_str::<AuthMessage>(text)
+            .ok()
+            .and_then(|msg| ctx.state().keys.authenticate(&msg.auth));
+
+        match key {
+            Some(key) => {
+                let credentials = Credentials::ApiKey(key);
+                self.credentials = Some(credentials.clone());
+                self.join(credentials, ctx);
+            },
+            None => {
+                self.close(CloseCode::Policy, "Invalid API Key", ctx);
+            },
+        }
+    }
+
+    fn close(&mut self, code: CloseCode, reason: &str, ctx: &mut <Self as Actor>::Context) {
+        ctx.close(Some(CloseReason { code, description: Some(reason.to_string()) }));
+        ctx.stop();
+    }
+
+    fn set_frames_behind(&mut self, frames_behind: u32) {
+        let changed = frames_behind != self.frames_behind;
+        self.frames_behind = frames_behind;
+
+        if !self.api_key.is_empty() && self.api_key != "SPECTATOR" {
+            METRICS.set_frames_behind(&self.api_key, Some(frames_behind));
+
+            if let (true, Destination::Room(game_addr)) = (changed, &self.destination) {
+                let api_key = self.api_key.clone();
+                game_addr.do_send(FramesBehind { api_key, frames_behind });
+            }
+        }
     }
 }
 
@@ -32,18 +157,32 @@ impl Actor for ClientWsActor {
     type Context = ws::WebsocketContext<Self, AppState>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        self.game_addr.do_send(crate::actors::game_actor::SocketEvent::Join(
-            self.api_key.clone(),
-            self.team_name.clone(),
-            ctx.address(),
-        ));
+        if let Some(credentials) = self.credentials.clone() {
+            self.join(credentials, ctx);
+        }
+
+        ctx.run_interval(HEARTBEAT_INTERVAL, |_, ctx| ctx.ping(""));
     }
 
     fn stopped(&mut self, ctx: &mut Self::Context) {
-        self.game_addr.do_send(crate::actors::game_actor::SocketEvent::Leave(
-            self.api_key.clone(),
-            ctx.address(),
-        ));
+        if self.api_key.is_empty() {
+            return;
+        }
+
+        if self.api_key != "SPECTATOR" {
+            METRICS.set_frames_behind(&self.api_key, None);
+        }
+
+        let api_key = self.api_key.clone();
+
+        match &self.destination {
+            Destination::Room(game_addr) => {
+                game_addr.do_send(SocketEvent::Leave(api_key, ctx.address()))
+            },
+            Destination::Queue(lobby_addr) => {
+                lobby_addr.do_send(QueueEvent::Leave(api_key, ctx.address()))
+            },
+        }
     }
 }
 
@@ -51,15 +190,29 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for ClientWsActor {
     fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
         match msg {
             ws::Message::Text(cmd) => {
-                if self.rate_limiter.check().is_ok() {
+                METRICS.record_message_in();
+
+                if self.api_key.is_empty() {
+                    self.authenticate(&cmd, ctx);
+                } else if self.rate_limiter.check().is_ok() {
                     let cmd_result = serde_json::from_str(&cmd);
 
-                    if let Ok(cmd) = cmd_result {
-                        self.game_addr
-                            .do_send(PlayerGameCommand { api_key: self.api_key.clone(), cmd });
+                    // There's no game to play while queued
+                    if let (Ok(cmd), Destination::Room(game_addr)) = (cmd_result, &self.destination)
+                    {
+                        game_addr.do_send(PlayerGameCommand { api_key: self.api_key.clone(), cmd });
                     }
                 } else {
                     warn!("API key {} got rate limited", self.api_key);
+                    METRICS.record_rate_limited(&self.api_key);
+                }
+            },
+            ws::Message::Pong(_) if !self.api_key.is_empty() => {
+                let heartbeat = Heartbeat { api_key: self.api_key.clone() };
+
+                match &self.destination {
+                    Destination::Room(game_addr) => game_addr.do_send(heartbeat),
+                    Destination::Queue(lobby_addr) => lobby_addr.do_send(heartbeat),
                 }
             },
             ws::Message::Close(_) => {
@@ -74,7 +227,89 @@ impl Handler<ServerToClient> for ClientWsActor {
     type Result = ();
 
     fn handle(&mut self, msg: ServerToClient, ctx: &mut Self::Context) {
-        ctx.text(serde_json::to_string(&msg).unwrap());
+        let serialization_start = Instant::now();
+        let text = serde_json::to_string(&msg).unwrap();
+        METRICS.record_serialization(serialization_start.elapsed());
+        METRICS.record_message_out();
+
+        ctx.text(text);
+    }
+}
+
+impl Handler<StateFrame> for ClientWsActor {
+    type Result = ();
+
+    fn handle(&mut self, frame: StateFrame, ctx: &mut Self::Context) {
+        if self.sending_frame {
+            // A slow socket only ever gets the newest state, the older ones
+            // are useless by the time it's ready
+            if self.pending_frame.replace(frame).is_some() {
+                METRICS.record_frame_dropped();
+                self.set_frames_behind(self.frames_behind + 1);
+
+                if self.frames_behind == SLOW_CLIENT_WARNING_FRAMES {
+                    let warning = ServerToClient::SlowConnection(self.frames_behind);
+                    ctx.text(serde_json::to_string(&warning).unwrap());
+                } else if self.frames_behind >= SLOW_CLIENT_MAX_FRAMES {
+                    // Unlike an authentication failure, it's worth trying again
+                    warn!("API key {} can't keep up, disconnecting", self.api_key);
+                    self.close(
+                        CloseCode::Again,
+                        "Your connection couldn't keep up with the game",
+                        ctx,
+                    );
+                }
+            }
+        } else {
+            self.send_frame(frame, ctx);
+        }
+    }
+}
+
+impl Handler<Authenticated> for ClientWsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Authenticated, ctx: &mut Self::Context) {
+        self.api_key = msg.key.id;
+        self.team_name = msg.team_name;
+
+        if let Some(rate_limit) = msg.key.rate_limit.and_then(NonZeroU32::new) {
+            self.rate_limiter = DirectRateLimiter::<GCRA>::per_second(rate_limit);
+        }
+
+        let session = ServerToClient::Session(msg.token);
+        ctx.text(serde_json::to_string(&session).unwrap());
+    }
+}
+
+impl Handler<Queued> for ClientWsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Queued, _ctx: &mut Self::Context) {
+        self.api_key = msg.key_id;
+        self.team_name = msg.team_name;
+    }
+}
+
+impl Handler<Redirect> for ClientWsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Redirect, ctx: &mut Self::Context) {
+        let redirect = ServerToClient::Redirect(msg.room.clone());
+        ctx.text(serde_json::to_string(&redirect).unwrap());
+
+        ctx.close(Some(CloseReason {
+            code: CloseCode::Normal,
+            description: Some(format!("Reconnect to the room {} for your match", msg.room)),
+        }));
+    }
+}
+
+impl Handler<AuthFailed> for ClientWsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: AuthFailed, ctx: &mut Self::Context) {
+        self.close(CloseCode::Policy, &msg.reason, ctx);
     }
 }
 
@@ -88,3 +323,14 @@ impl Handler<ClientStop> for ClientWsActor {
         }));
     }
 }
+
+impl Handler<ServerRestarting> for ClientWsActor {
+    type Result = ();
+
+    fn handle(&mut self, _: ServerRestarting, ctx: &mut Self::Context) {
+        ctx.close(Some(CloseReason {
+            code: CloseCode::Restart,
+            description: Some("The server is restarting, reconnect in a few seconds".to_string()),
+        }));
+    }
+}
@@ -1,27 +1,108 @@
 use crate::{
     actors::ClientWsActor,
-    game::{Game, TICKS_PER_SECOND},
-    models::messages::{ClientStop, PlayerGameCommand},
+    bots::{Bot, BotConfig, BOT_TAG},
+    chat,
+    game::{Game, GameConfig, TICKS_PER_SECOND},
+    health::{LoopHealth, HEALTH},
+    keys::{ApiKey, KeyStore, Role},
+    map::Map,
+    metrics::METRICS,
+    models::messages::{
+        AddBot, AuthFailed, Authenticated, BotSummary, ChatUpdate, Checkpoint, ClientStop,
+        CloseRoom, CommandRejected, CreateSession, Credentials, FramesBehind, GetStandings,
+        GetStats, Heartbeat, ListBots, ListPlayers, PlayerGameCommand, PlayerSummary, Redirect,
+        RedirectPlayers, RemoveBot, ServerRestarting, ShutDown, StateFrame, StatsUpdate,
+        TeamNamesUpdate, TeamStats, TournamentUpdate,
+    },
+    ratings::{Outcome, RatingStore},
+    recording::{Recorder, Recording},
+    snapshot::Snapshot,
+    tournament::RoomResult,
 };
-use actix::{Actor, Addr, AsyncContext, Context, Handler, Message};
+use actix::{Actor, ActorContext, Addr, AsyncContext, Context, Handler, Message, MessageResult};
 use futures::sync::oneshot;
+use rand::{distributions::Alphanumeric, Rng};
 use spin_sleep::LoopHelper;
 use std::{
     collections::{HashMap, HashSet},
-    sync::mpsc::{channel, Receiver, Sender},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 use tokyo::models::*;
 
+// Send statistics to spectators about once a second
+const STATS_INTERVAL_TICKS: u64 = TICKS_PER_SECOND as u64;
+
+// Session tokens stay valid for this long after they were last used
+pub const SESSION_TTL: Duration = Duration::from_secs(10 * 60);
+
+// A connection which hasn't sent anything for this long is presumed dead, and
+// can be replaced by a new connection with the same API key
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(15);
+
+// How often to check on the game loop, and how many times it can crash before
+// we stop restarting it
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_GAME_LOOP_RESTARTS: u32 = 3;
+
+/// Marks the team names of people playing from the browser, with `/?play`.
+pub const HUMAN_TAG: &str = "[HUMAN]";
+
+// Rooms other than tournament matches rate everybody who played this often
+const RATING_ROUND: Duration = Duration::from_secs(5 * 60);
+
 #[derive(Debug)]
 pub struct GameActor {
+    room: String,
+    health: Arc<LoopHealth>,
     connections: HashMap<String, Addr<ClientWsActor>>,
     spectators: HashSet<Addr<ClientWsActor>>,
     team_names: HashMap<u32, String>,
     cancel_chan: Option<oneshot::Sender<()>>,
-    msg_tx: Sender<GameLoopCommand>,
-    msg_rx: Option<Receiver<GameLoopCommand>>,
+    game_loop: Option<JoinHandle<(GameState, HashMap<u32, PlayerStats>)>>,
+    msg_tx: Option<Sender<GameLoopCommand>>,
+    map: Option<Map>,
+    game_config: Option<GameConfig>,
+    // The latest state reported by the game loop, to start it again from
+    checkpoint: Option<(GameState, HashMap<u32, PlayerStats>)>,
+    restarts: u32,
     player_id_counter: u32,
     api_key_to_player_id: HashMap<String, u32>,
+    // Bots hosted by the game loop, which get player IDs like everybody else
+    bots: HashMap<u32, BotConfig>,
+    stats: HashMap<u32, PlayerStats>,
+    sessions: HashMap<String, Session>,
+    // The session token of each connected API key, when we last heard from
+    // it, and how far its socket fell behind
+    connection_sessions: HashMap<String, String>,
+    last_seen: HashMap<String, Instant>,
+    frames_behind: HashMap<String, u32>,
+    keys: Arc<KeyStore>,
+    ratings: Arc<RatingStore>,
+    // The points and kills of each player when they joined the current
+    // rating round
+    rating_baseline: HashMap<u32, (u32, u32)>,
+    // Set for tournament matches, which only their players can join
+    allowed_keys: Option<HashSet<String>>,
+    // The latest bracket, for spectators who join while a tournament runs
+    tournament: Option<Tournament>,
+    // When each player last said something, for rate limiting chat
+    last_chat: HashMap<u32, Instant>,
+    snapshot_file: Option<String>,
+    recorder: Option<Recorder>,
+    // Set when replaying a recording instead of running a game
+    replay: Option<Recording>,
+    replaying: bool,
+}
+
+#[derive(Debug)]
+struct Session {
+    key: ApiKey,
+    expires: Instant,
 }
 
 #[derive(Debug)]
@@ -29,21 +110,339 @@ pub enum GameLoopCommand {
     PlayerJoined(u32),
     PlayerLeft(u32),
     GameCommand(u32, GameCommand),
+    AddBot(u32, BotConfig),
+    RemoveBot(u32),
 }
 
 impl GameActor {
-    pub fn new() -> GameActor {
-        let (msg_tx, msg_rx) = channel();
+    pub fn new(
+        room: &str,
+        map: Map,
+        game_config: GameConfig,
+        keys: Arc<KeyStore>,
+        ratings: Arc<RatingStore>,
+    ) -> GameActor {
+        let mut actor = GameActor::empty(room, keys, ratings);
+        actor.map = Some(map);
+        actor.game_config = Some(game_config);
+
+        actor
+    }
+
+    /// Continues the game from a snapshot if there is one, and saves a new
+    /// one to `snapshot_file` when the server shuts down.
+    pub fn with_snapshot(
+        mut self,
+        snapshot: Option<Snapshot>,
+        snapshot_file: Option<String>,
+    ) -> GameActor {
+        self.snapshot_file = snapshot_file;
+
+        if let Some(snapshot) = snapshot {
+            self.team_names = snapshot.team_names;
+            self.player_id_counter = snapshot.player_id_counter;
+            self.api_key_to_player_id = snapshot.api_key_to_player_id;
+            self.bots = snapshot.bots;
+            self.stats = snapshot.stats.clone();
+            self.checkpoint = Some((snapshot.game_state, snapshot.stats));
+        }
+
+        self
+    }
+
+    pub fn with_recorder(mut self, recorder: Option<Recorder>) -> GameActor {
+        self.recorder = recorder;
+        self.record(&ServerToClient::TeamNames(self.team_names.clone()));
+
+        self
+    }
 
+    pub fn with_allowed_keys(mut self, allowed_keys: Option<HashSet<String>>) -> GameActor {
+        self.allowed_keys = allowed_keys;
+        self
+    }
+
+    /// Plays back a recording to spectators, players can't join.
+    pub fn replay(
+        room: &str,
+        recording: Recording,
+        keys: Arc<KeyStore>,
+        ratings: Arc<RatingStore>,
+    ) -> GameActor {
+        let mut actor = GameActor::empty(room, keys, ratings);
+        actor.replay = Some(recording);
+        actor.replaying = true;
+
+        actor
+    }
+
+    fn empty(room: &str, keys: Arc<KeyStore>, ratings: Arc<RatingStore>) -> GameActor {
         GameActor {
+            room: room.to_string(),
+            health: HEALTH.register(room),
             connections: HashMap::new(),
             spectators: HashSet::new(),
             team_names: HashMap::new(),
             cancel_chan: None,
-            msg_tx,
-            msg_rx: Some(msg_rx),
+            game_loop: None,
+            msg_tx: None,
+            map: None,
+            game_config: None,
+            checkpoint: None,
+            restarts: 0,
             player_id_counter: 0,
             api_key_to_player_id: HashMap::new(),
+            bots: HashMap::new(),
+            stats: HashMap::new(),
+            sessions: HashMap::new(),
+            connection_sessions: HashMap::new(),
+            last_seen: HashMap::new(),
+            frames_behind: HashMap::new(),
+            keys,
+            ratings,
+            rating_baseline: HashMap::new(),
+            allowed_keys: None,
+            tournament: None,
+            last_chat: HashMap::new(),
+            snapshot_file: None,
+            recorder: None,
+            replay: None,
+            replaying: false,
+        }
+    }
+
+    fn record(&mut self, msg: &ServerToClient) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.write(msg);
+        }
+    }
+
+    // Cleans up a chat message and sends it to everybody in the room, unless
+    // the player is chatting too fast
+    fn say(&mut self, player_id: u32, text: &str, ctx: &mut Context<Self>) {
+        let game_config = match &self.game_config {
+            Some(game_config) => game_config,
+            None => return,
+        };
+
+        let now = Instant::now();
+        let interval = Duration::from_millis((game_config.chat_interval_secs * 1000.0) as u64);
+        let result = match self.last_chat.get(&player_id) {
+            Some(last_chat) if now - *last_chat < interval => Err(Rejection::ChattingTooFast),
+            _ => chat::clean(text, game_config),
+        };
+
+        match result {
+            Ok(Some(text)) => {
+                self.last_chat.insert(player_id, now);
+                self.broadcast_chat(ChatMessage { player_id, text });
+            },
+            Ok(None) => {},
+            Err(rejection) => ctx.notify(CommandRejected { player_id, rejection }),
+        }
+    }
+
+    fn broadcast_chat(&mut self, message: ChatMessage) {
+        self.record(&ServerToClient::Chat(message.clone()));
+
+        for addr in self.connections.values().chain(self.spectators.iter()) {
+            addr.do_send(ServerToClient::Chat(message.clone()));
+        }
+    }
+
+    fn broadcast_team_names(&mut self) {
+        self.record(&ServerToClient::TeamNames(self.team_names.clone()));
+        for addr in self.connections.values().chain(self.spectators.iter()) {
+            addr.do_send(ServerToClient::TeamNames(self.team_names.clone()));
+        }
+    }
+
+    // Tell everyone we're restarting, stop the game loop, and save the game
+    // so it can continue after the restart
+    fn shut_down(&mut self) {
+        info!("Shutting down room {}", self.room);
+
+        for addr in self.connections.values().chain(self.spectators.iter()) {
+            addr.do_send(ServerRestarting {});
+        }
+
+        let final_state = self.stop_game_loop();
+
+        if let (Some((game_state, stats)), Some(path)) = (final_state, &self.snapshot_file) {
+            let snapshot = Snapshot {
+                game_state,
+                stats,
+                api_key_to_player_id: self.api_key_to_player_id.clone(),
+                bots: self.bots.clone(),
+                team_names: self.team_names.clone(),
+                player_id_counter: self.player_id_counter,
+            };
+
+            match snapshot.save(path) {
+                Ok(()) => info!("Saved a snapshot of the game to {}", path),
+                Err(e) => error!("Could not save a snapshot of the game to {}: {}", path, e),
+            }
+        }
+    }
+
+    // Returns the final state of the game, or the last checkpoint if the game
+    // loop crashed
+    fn stop_game_loop(&mut self) -> Option<(GameState, HashMap<u32, PlayerStats>)> {
+        if let Some(cancel_chan) = self.cancel_chan.take() {
+            let _ = cancel_chan.send(());
+        }
+
+        let final_state = self
+            .game_loop
+            .take()
+            .and_then(|game_loop| game_loop.join().ok())
+            .or_else(|| self.checkpoint.take());
+        METRICS.commands_dropped(self.health.take_queued());
+        self.health.finish();
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.flush();
+        }
+
+        final_state
+    }
+
+    fn create_session(&mut self, key: &ApiKey) -> String {
+        let now = Instant::now();
+        self.sessions.retain(|_, session| session.expires > now);
+
+        let token: String = rand::thread_rng().sample_iter(&Alphanumeric).take(32).collect();
+        let session = Session { key: key.clone(), expires: now + SESSION_TTL };
+        self.sessions.insert(token.clone(), session);
+
+        token
+    }
+
+    fn refresh_session(&mut self, api_key: &str) {
+        if let Some(token) = self.connection_sessions.get(api_key) {
+            if let Some(session) = self.sessions.get_mut(token) {
+                session.expires = Instant::now() + SESSION_TTL;
+            }
+        }
+    }
+
+    fn bot_summary(&self, player_id: u32, config: BotConfig) -> BotSummary {
+        let team_name = self.team_names.get(&player_id).cloned().unwrap_or_default();
+        BotSummary { player_id, team_name, config }
+    }
+
+    // As of the last checkpoint
+    fn points_and_kills(&self, player_id: u32) -> (u32, u32) {
+        let points = self
+            .checkpoint
+            .as_ref()
+            .and_then(|(game_state, _)| game_state.scoreboard.get(&player_id).cloned())
+            .unwrap_or(0);
+        let kills = self.stats.get(&player_id).map_or(0, |stats| stats.kills);
+
+        (points, kills)
+    }
+
+    // Rates everybody who played since the last rating round on the points
+    // and kills they got in it, and starts the next one
+    fn end_rating_round(&mut self) {
+        let outcomes: Vec<Outcome> = self
+            .api_key_to_player_id
+            .iter()
+            .filter_map(|(api_key, player_id)| {
+                let (start_points, start_kills) = *self.rating_baseline.get(player_id)?;
+                let (points, kills) = self.points_and_kills(*player_id);
+
+                Some(Outcome {
+                    key_id: api_key.clone(),
+                    team_name: self.team_names.get(player_id).cloned().unwrap_or_default(),
+                    points: points.saturating_sub(start_points),
+                    kills: kills.saturating_sub(start_kills),
+                })
+            })
+            .collect();
+
+        self.ratings.record(&outcomes);
+
+        self.rating_baseline = self
+            .connections
+            .keys()
+            .filter_map(|api_key| self.api_key_to_player_id.get(api_key))
+            .map(|player_id| (*player_id, self.points_and_kills(*player_id)))
+            .collect();
+    }
+
+    fn is_healthy(&self, api_key: &str) -> bool {
+        self.last_seen.get(api_key).is_some_and(|time| time.elapsed() < CONNECTION_TIMEOUT)
+    }
+
+    fn start_game_loop(&mut self, ctx: &mut Context<Self>) {
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let (msg_tx, msg_rx) = channel();
+        let addr = ctx.address();
+
+        let map = self.map.clone().unwrap();
+        let game_config = self.game_config.clone().unwrap();
+        let checkpoint = self.checkpoint.clone();
+        let health = self.health.clone();
+
+        self.game_loop = Some(std::thread::spawn(move || {
+            game_loop(addr, msg_rx, cancel_rx, map, game_config, checkpoint, health)
+        }));
+
+        self.msg_tx = Some(msg_tx);
+        self.cancel_chan = Some(cancel_tx);
+
+        // Bots only live in the game loop, so a new one needs them again
+        for (player_id, bot) in &self.bots {
+            self.send_to_game_loop(GameLoopCommand::AddBot(*player_id, *bot));
+        }
+    }
+
+    // Restarts the game loop from the last checkpoint if it crashed
+    fn check_game_loop(&mut self, ctx: &mut Context<Self>) {
+        if !self.health.take_crash() {
+            return;
+        }
+
+        // The thread is already unwinding, so this doesn't block for long
+        if let Some(game_loop) = self.game_loop.take() {
+            let _ = game_loop.join();
+        }
+        METRICS.commands_dropped(self.health.take_queued());
+
+        if self.restarts >= MAX_GAME_LOOP_RESTARTS {
+            error!("The game loop crashed again, giving up after {} restarts", self.restarts);
+            self.health.give_up();
+            return;
+        }
+
+        warn!("The game loop crashed, restarting it from the last checkpoint");
+        self.restarts += 1;
+        self.health.record_restart();
+        self.start_game_loop(ctx);
+
+        // Restoring the checkpoint marks everybody as disconnected
+        let player_ids: Vec<u32> = self
+            .connections
+            .keys()
+            .filter_map(|api_key| self.api_key_to_player_id.get(api_key).cloned())
+            .collect();
+
+        for player_id in player_ids {
+            self.send_to_game_loop(GameLoopCommand::PlayerJoined(player_id));
+        }
+    }
+
+    fn send_to_game_loop(&self, cmd: GameLoopCommand) {
+        // The game loop isn't running while the server shuts down, or after
+        // it crashed
+        let sent = self.msg_tx.as_ref().is_some_and(|msg_tx| msg_tx.send(cmd).is_ok());
+        if sent {
+            self.health.record_queued();
+            METRICS.command_queued();
+        } else {
+            warn!("The game loop isn't running, dropping a command");
         }
     }
 }
@@ -52,15 +451,27 @@ fn game_loop(
     game_actor: Addr<GameActor>,
     msg_chan: Receiver<GameLoopCommand>,
     mut cancel_chan: oneshot::Receiver<()>,
-) {
+    map: Map,
+    game_config: GameConfig,
+    checkpoint: Option<(GameState, HashMap<u32, PlayerStats>)>,
+    health: Arc<LoopHealth>,
+) -> (GameState, HashMap<u32, PlayerStats>) {
+    let _crash_guard = CrashGuard(health.clone());
     let mut loop_helper = LoopHelper::builder().build_with_target_rate(TICKS_PER_SECOND);
+    let tick_interval = Duration::from_micros((1_000_000.0 / TICKS_PER_SECOND) as u64);
 
-    let mut game = Game::default();
+    let mut game = Game::new(map, game_config);
+    let mut bots: HashMap<u32, Bot> = HashMap::new();
+
+    if let Some((game_state, stats)) = checkpoint {
+        game.restore(game_state, stats);
+    }
 
     game.init();
 
     loop {
         loop_helper.loop_start();
+        let tick_start = Instant::now();
 
         match cancel_chan.try_recv() {
             Ok(Some(_)) | Err(_) => {
@@ -70,6 +481,8 @@ fn game_loop(
         }
 
         for cmd in msg_chan.try_iter() {
+            health.record_dequeued();
+            METRICS.command_dequeued();
             // info!("Got a message! - {:?}", cmd);
             match cmd {
                 GameLoopCommand::PlayerJoined(id) => {
@@ -79,48 +492,171 @@ fn game_loop(
                     game.player_left(id);
                 },
                 GameLoopCommand::GameCommand(id, cmd) => {
-                    game.handle_cmd(id, cmd);
+                    if let Err(rejection) = game.handle_cmd(id, cmd) {
+                        game_actor.do_send(CommandRejected { player_id: id, rejection });
+                    }
                 },
+                GameLoopCommand::AddBot(id, config) => {
+                    game.add_player(id);
+                    bots.insert(id, Bot::new(config));
+                },
+                GameLoopCommand::RemoveBot(id) => {
+                    game.player_left(id);
+                    bots.remove(&id);
+                },
+            }
+        }
+
+        // Bots see the state from the end of the last tick, like the
+        // websocket clients do
+        if !bots.is_empty() {
+            let mut client_state =
+                ClientState { id: 0, game_state: game.state.clone(), rejected: None, chat: vec![] };
+
+            for (id, bot) in &mut bots {
+                if !game.state.players.iter().any(|player| player.id == *id) {
+                    continue;
+                }
+
+                client_state.id = *id;
+                if let Some(cmd) = bot.tick(&mut client_state) {
+                    if let Err(rejection) = game.handle_cmd(*id, cmd) {
+                        bot.rejected(rejection);
+                    }
+                }
             }
         }
 
         let dt = 1.0 / TICKS_PER_SECOND;
         game.tick(dt);
 
-        // Send out update packets
+        // Send out update packets, serialized once for everybody
+        let serialization_start = Instant::now();
+        let json = serde_json::to_string(&ServerToClient::GameState(game.state.clone()))
+            .expect("The game state should always be serializable");
+        METRICS.record_serialization(serialization_start.elapsed());
+
+        game_actor.do_send(StateFrame { json: Arc::new(json) });
+
+        if game.state.tick.is_multiple_of(STATS_INTERVAL_TICKS) {
+            let stats = game.stats();
+            game_actor.do_send(Checkpoint { game_state: game.state.clone(), stats: stats.clone() });
+            game_actor.do_send(StatsUpdate { stats });
+        }
 
-        // TODO(bschwind) - maybe put the game state behind an Arc
-        //                  instead of cloning it
-        game_actor.do_send(game.state.clone());
+        METRICS.record_tick(tick_start.elapsed(), tick_interval);
+        health.record_tick();
         loop_helper.loop_sleep();
     }
 
     info!("game over!");
+
+    let stats = game.stats();
+    (game.state, stats)
+}
+
+// Sends out the messages of a recording at the speed they were recorded
+fn replay_loop(
+    game_actor: Addr<GameActor>,
+    mut cancel_chan: oneshot::Receiver<()>,
+    recording: Recording,
+    health: Arc<LoopHealth>,
+) -> (GameState, HashMap<u32, PlayerStats>) {
+    let mut loop_helper = LoopHelper::builder().build_with_target_rate(TICKS_PER_SECOND);
+    let mut last_state = GameState::default();
+    let mut last_stats = HashMap::new();
+
+    for line in recording {
+        match cancel_chan.try_recv() {
+            Ok(Some(_)) | Err(_) => {
+                break;
+            },
+            _ => {},
+        }
+
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Could not read the recording: {}", e);
+                break;
+            },
+        };
+
+        match serde_json::from_str(&line) {
+            Ok(ServerToClient::GameState(state)) => {
+                loop_helper.loop_start();
+                game_actor.do_send(StateFrame { json: Arc::new(line) });
+                health.record_tick();
+                last_state = state;
+                loop_helper.loop_sleep();
+            },
+            Ok(ServerToClient::TeamNames(team_names)) => {
+                game_actor.do_send(TeamNamesUpdate { team_names });
+            },
+            Ok(ServerToClient::Stats(stats)) => {
+                last_stats = stats.clone();
+                game_actor.do_send(StatsUpdate { stats });
+            },
+            Ok(ServerToClient::Tournament(tournament)) => {
+                game_actor.do_send(TournamentUpdate { tournament });
+            },
+            Ok(ServerToClient::Chat(message)) => {
+                game_actor.do_send(ChatUpdate { message });
+            },
+            Ok(_) => {},
+            Err(e) => {
+                warn!("Skipping a broken line of the recording: {}", e);
+            },
+        }
+    }
+
+    info!("replay over!");
+    health.finish();
+
+    (last_state, last_stats)
 }
 
 impl Actor for GameActor {
     type Context = Context<GameActor>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        info!("Game Actor started!");
-        let (cancel_tx, cancel_rx) = oneshot::channel();
-        let addr = ctx.address();
+        info!("Game Actor started for room {}!", self.room);
 
-        // "Take" the receiving end of the channel and give it
-        // to the game loop thread
-        let msg_rx = self.msg_rx.take().unwrap();
+        if let Some(recording) = self.replay.take() {
+            let (cancel_tx, cancel_rx) = oneshot::channel();
+            let addr = ctx.address();
+            let health = self.health.clone();
 
-        std::thread::spawn(move || {
-            game_loop(addr, msg_rx, cancel_rx);
-        });
+            self.game_loop =
+                Some(std::thread::spawn(move || replay_loop(addr, cancel_rx, recording, health)));
+            self.cancel_chan = Some(cancel_tx);
+        } else {
+            self.start_game_loop(ctx);
+            ctx.run_interval(WATCHDOG_INTERVAL, |act, ctx| act.check_game_loop(ctx));
 
-        self.cancel_chan = Some(cancel_tx);
+            // Tournament matches are rated by the tournament
+            if self.allowed_keys.is_none() {
+                ctx.run_interval(RATING_ROUND, |act, _| act.end_rating_round());
+            }
+        }
+    }
+}
+
+// Lets the watchdog know when the game loop panics
+struct CrashGuard(Arc<LoopHealth>);
+
+impl Drop for CrashGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.record_crash();
+        }
     }
 }
 
 #[derive(Debug, Message)]
 pub enum SocketEvent {
-    Join(String, String, Addr<ClientWsActor>),
+    // With the requested team name
+    Join(Credentials, String, Addr<ClientWsActor>),
     Leave(String, Addr<ClientWsActor>),
 }
 
@@ -129,50 +665,146 @@ impl Handler<SocketEvent> for GameActor {
 
     fn handle(&mut self, msg: SocketEvent, _ctx: &mut Self::Context) {
         match msg {
-            SocketEvent::Join(api_key, team_name, addr) => {
+            SocketEvent::Join(Credentials::Spectator, _, addr) => {
+                info!("spectator joined");
+
+                addr.do_send(ServerToClient::TeamNames(self.team_names.clone()));
+                addr.do_send(ServerToClient::Stats(self.stats.clone()));
+                if let Some(tournament) = &self.tournament {
+                    addr.do_send(ServerToClient::Tournament(tournament.clone()));
+                }
+                self.spectators.insert(addr);
+            },
+            SocketEvent::Join(credentials, team_name, addr) => {
+                let (key, resumed_token) = match credentials {
+                    Credentials::Session(token, fallback_key) => {
+                        let now = Instant::now();
+                        let keys = &self.keys;
+                        let session = self
+                            .sessions
+                            .get(&token)
+                            .filter(|session| session.expires > now && keys.is_valid(&session.key));
+
+                        // A client which was away for too long starts a new
+                        // session with its API key
+                        match (session, fallback_key) {
+                            (Some(session), _) => (session.key.clone(), Some(token)),
+                            (None, Some(key)) => (key, None),
+                            (None, None) => {
+                                let reason = "Unknown or expired session token".to_string();
+                                addr.do_send(AuthFailed { reason });
+                                return;
+                            },
+                        }
+                    },
+                    Credentials::ApiKey(key) => (key, None),
+                    Credentials::Spectator => unreachable!(),
+                };
+
+                if self.replaying {
+                    let reason = "This server is replaying a recorded game".to_string();
+                    addr.do_send(AuthFailed { reason });
+                    return;
+                }
+
+                if key.role == Role::Spectator {
+                    addr.do_send(AuthFailed { reason: "This API key can only spectate".into() });
+                    return;
+                }
+
+                let allowed = match &self.allowed_keys {
+                    Some(allowed_keys) => allowed_keys.contains(&key.id),
+                    None => key.rooms.is_empty() || key.rooms.contains(&self.room),
+                };
+
+                if !allowed {
+                    let reason = format!("This API key can't join the room {}", self.room);
+                    addr.do_send(AuthFailed { reason });
+                    return;
+                }
+
+                let team_name = match self.keys.team_name(&key, &team_name) {
+                    Ok(team_name) => team_name,
+                    Err(reason) => {
+                        addr.do_send(AuthFailed { reason });
+                        return;
+                    },
+                };
+
+                if team_name.starts_with(BOT_TAG) || team_name.starts_with(HUMAN_TAG) {
+                    let reason =
+                        format!("Team names can't start with {} or {}", BOT_TAG, HUMAN_TAG);
+                    addr.do_send(AuthFailed { reason });
+                    return;
+                }
+
+                // So everybody can tell people from bots
+                let team_name = if key.role == Role::Human {
+                    format!("{} {}", HUMAN_TAG, team_name)
+                } else {
+                    team_name
+                };
+
+                let api_key = key.id.clone();
                 let key_clone = api_key.clone();
                 let addr_clone = addr.clone();
 
                 info!("person joined - {:?}", api_key);
 
-                if api_key == "SPECTATOR" {
-                    addr.do_send(ServerToClient::TeamNames(self.team_names.clone()));
-                    self.spectators.insert(addr);
-                } else {
-                    let existing_client_opt = self.connections.insert(api_key, addr);
+                // Only the same session can take over a connection which is
+                // still alive, anything else is probably a second copy of
+                // the same bot
+                if self.connections.contains_key(&api_key) {
+                    let same_session = resumed_token.is_some()
+                        && self.connection_sessions.get(&api_key) == resumed_token.as_ref();
 
-                    if let Some(existing_client) = existing_client_opt {
-                        info!("kicking out old connection");
-                        existing_client.do_send(ClientStop {});
+                    if !same_session && self.is_healthy(&api_key) {
+                        let reason = "This API key is already connected".to_string();
+                        addr.do_send(AuthFailed { reason });
+                        return;
                     }
+                }
 
-                    let player_id =
-                        if let Some(player_id) = self.api_key_to_player_id.get(&key_clone) {
-                            addr_clone.do_send(ServerToClient::Id(*player_id));
-                            *player_id
-                        } else {
-                            // This was the first time this API key connected,
-                            // assign them a player ID and return it
-                            let player_id = self.player_id_counter;
-                            self.player_id_counter += 1;
-                            info!("API key {} gets player ID {}", key_clone, player_id);
-
-                            self.api_key_to_player_id.insert(key_clone, player_id);
-
-                            self.msg_tx
-                                .send(GameLoopCommand::PlayerJoined(player_id))
-                                .expect("The game loop should always be receiving commands");
-
-                            addr_clone.do_send(ServerToClient::Id(player_id));
-                            player_id
-                        };
-
-                    // Update team name and broadcast new team names list to all sockets.
-                    self.team_names.insert(player_id, team_name);
-                    for addr in self.connections.values().chain(self.spectators.iter()) {
-                        addr.do_send(ServerToClient::TeamNames(self.team_names.clone()));
-                    }
+                let token = resumed_token.unwrap_or_else(|| self.create_session(&key));
+                self.connection_sessions.insert(api_key.clone(), token.clone());
+                self.last_seen.insert(api_key.clone(), Instant::now());
+                self.frames_behind.remove(&api_key);
+                self.refresh_session(&api_key);
+                addr.do_send(Authenticated { key, team_name: team_name.clone(), token });
+
+                let existing_client_opt = self.connections.insert(api_key, addr);
+
+                if let Some(existing_client) = existing_client_opt {
+                    info!("kicking out old connection");
+                    existing_client.do_send(ClientStop {});
                 }
+
+                let player_id = if let Some(player_id) = self.api_key_to_player_id.get(&key_clone) {
+                    addr_clone.do_send(ServerToClient::Id(*player_id));
+                    *player_id
+                } else {
+                    // This was the first time this API key connected,
+                    // assign them a player ID and return it
+                    let player_id = self.player_id_counter;
+                    self.player_id_counter += 1;
+                    info!("API key {} gets player ID {}", key_clone, player_id);
+
+                    self.api_key_to_player_id.insert(key_clone, player_id);
+
+                    addr_clone.do_send(ServerToClient::Id(player_id));
+                    player_id
+                };
+
+                // Also sent for returning players, who might have been
+                // removed from the game while they were away
+                self.send_to_game_loop(GameLoopCommand::PlayerJoined(player_id));
+
+                let points_and_kills = self.points_and_kills(player_id);
+                self.rating_baseline.entry(player_id).or_insert(points_and_kills);
+
+                // Update team name and broadcast new team names list to all sockets.
+                self.team_names.insert(player_id, team_name);
+                self.broadcast_team_names();
             },
             SocketEvent::Leave(api_key, addr) => {
                 if api_key == "SPECTATOR" {
@@ -182,10 +814,14 @@ impl Handler<SocketEvent> for GameActor {
                         if addr == *client_addr {
                             info!("person left - {:?}", api_key);
 
+                            // The session can still be resumed for a while
+                            self.refresh_session(&api_key);
+                            self.connection_sessions.remove(&api_key);
+                            self.last_seen.remove(&api_key);
+                            self.frames_behind.remove(&api_key);
+
                             if let Some(player_id) = self.api_key_to_player_id.get(&api_key) {
-                                self.msg_tx
-                                    .send(GameLoopCommand::PlayerLeft(*player_id))
-                                    .expect("The game loop should always be receiving commands");
+                                self.send_to_game_loop(GameLoopCommand::PlayerLeft(*player_id));
                             }
 
                             self.connections.remove(&api_key);
@@ -194,27 +830,295 @@ impl Handler<SocketEvent> for GameActor {
                 }
             },
         }
+
+        METRICS.set_connections(&self.room, Some((self.connections.len(), self.spectators.len())));
     }
 }
 
 impl Handler<PlayerGameCommand> for GameActor {
     type Result = ();
 
-    fn handle(&mut self, msg: PlayerGameCommand, _ctx: &mut Self::Context) {
-        if let Some(player_id) = self.api_key_to_player_id.get(&msg.api_key) {
-            self.msg_tx
-                .send(GameLoopCommand::GameCommand(*player_id, msg.cmd))
-                .expect("The game loop should always be receiving commands");
+    fn handle(&mut self, msg: PlayerGameCommand, ctx: &mut Self::Context) {
+        self.last_seen.insert(msg.api_key.clone(), Instant::now());
+
+        if let Some(player_id) = self.api_key_to_player_id.get(&msg.api_key).cloned() {
+            match msg.cmd {
+                GameCommand::Say(text) => self.say(player_id, &text, ctx),
+                cmd => self.send_to_game_loop(GameLoopCommand::GameCommand(player_id, cmd)),
+            }
+        }
+    }
+}
+
+impl Handler<StateFrame> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: StateFrame, _ctx: &mut Self::Context) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.write_json(&msg.json);
+        }
+
+        for addr in self.connections.values().chain(self.spectators.iter()) {
+            addr.do_send(msg.clone());
+        }
+    }
+}
+
+impl Handler<TeamNamesUpdate> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: TeamNamesUpdate, _ctx: &mut Self::Context) {
+        for addr in &self.spectators {
+            addr.do_send(ServerToClient::TeamNames(msg.team_names.clone()));
+        }
+
+        self.team_names = msg.team_names;
+    }
+}
+
+impl Handler<Checkpoint> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Checkpoint, _ctx: &mut Self::Context) {
+        self.checkpoint = Some((msg.game_state, msg.stats));
+    }
+}
+
+impl Handler<CommandRejected> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CommandRejected, _ctx: &mut Self::Context) {
+        let api_key = self
+            .api_key_to_player_id
+            .iter()
+            .find(|(_, player_id)| **player_id == msg.player_id)
+            .map(|(api_key, _)| api_key);
+
+        if let Some(addr) = api_key.and_then(|api_key| self.connections.get(api_key)) {
+            addr.do_send(ServerToClient::Rejected(msg.rejection));
+        }
+    }
+}
+
+impl Handler<ChatUpdate> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ChatUpdate, _ctx: &mut Self::Context) {
+        self.broadcast_chat(msg.message);
+    }
+}
+
+impl Handler<StatsUpdate> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: StatsUpdate, _ctx: &mut Self::Context) {
+        self.record(&ServerToClient::Stats(msg.stats.clone()));
+
+        for addr in &self.spectators {
+            addr.do_send(ServerToClient::Stats(msg.stats.clone()));
+        }
+
+        self.stats = msg.stats;
+    }
+}
+
+impl Handler<GetStats> for GameActor {
+    type Result = MessageResult<GetStats>;
+
+    fn handle(&mut self, _msg: GetStats, _ctx: &mut Self::Context) -> Self::Result {
+        let team_names = &self.team_names;
+
+        MessageResult(
+            self.stats
+                .iter()
+                .map(|(player_id, stats)| {
+                    let team_name = team_names.get(player_id).cloned().unwrap_or_default();
+                    (*player_id, TeamStats { team_name, stats: stats.clone() })
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Handler<CreateSession> for GameActor {
+    type Result = String;
+
+    fn handle(&mut self, msg: CreateSession, _ctx: &mut Self::Context) -> Self::Result {
+        self.create_session(&msg.key)
+    }
+}
+
+impl Handler<Heartbeat> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Heartbeat, _ctx: &mut Self::Context) {
+        if self.connections.contains_key(&msg.api_key) {
+            self.last_seen.insert(msg.api_key.clone(), Instant::now());
+            self.refresh_session(&msg.api_key);
         }
     }
 }
 
-impl Handler<GameState> for GameActor {
+impl Handler<FramesBehind> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: FramesBehind, _ctx: &mut Self::Context) {
+        if self.connections.contains_key(&msg.api_key) {
+            self.frames_behind.insert(msg.api_key, msg.frames_behind);
+        }
+    }
+}
+
+impl Handler<AddBot> for GameActor {
+    type Result = MessageResult<AddBot>;
+
+    fn handle(&mut self, msg: AddBot, _ctx: &mut Self::Context) -> Self::Result {
+        let player_id = self.player_id_counter;
+        self.player_id_counter += 1;
+        info!("Adding a {:?} bot as player ID {} in room {}", msg.config, player_id, self.room);
+
+        self.bots.insert(player_id, msg.config);
+        self.send_to_game_loop(GameLoopCommand::AddBot(player_id, msg.config));
+
+        self.team_names.insert(player_id, msg.config.team_name(player_id));
+        self.broadcast_team_names();
+
+        MessageResult(self.bot_summary(player_id, msg.config))
+    }
+}
+
+impl Handler<RemoveBot> for GameActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: RemoveBot, _ctx: &mut Self::Context) -> Self::Result {
+        // The team name stays, like it does for players who left, so their
+        // stats still have a name
+        let removed = self.bots.remove(&msg.player_id).is_some();
+        if removed {
+            info!("Removing the bot with player ID {} in room {}", msg.player_id, self.room);
+            self.send_to_game_loop(GameLoopCommand::RemoveBot(msg.player_id));
+        }
+
+        removed
+    }
+}
+
+impl Handler<ListBots> for GameActor {
+    type Result = MessageResult<ListBots>;
+
+    fn handle(&mut self, _msg: ListBots, _ctx: &mut Self::Context) -> Self::Result {
+        let mut bots: Vec<BotSummary> = self
+            .bots
+            .iter()
+            .map(|(player_id, config)| self.bot_summary(*player_id, *config))
+            .collect();
+        bots.sort_by_key(|bot| bot.player_id);
+
+        MessageResult(bots)
+    }
+}
+
+impl Handler<ListPlayers> for GameActor {
+    type Result = MessageResult<ListPlayers>;
+
+    fn handle(&mut self, _msg: ListPlayers, _ctx: &mut Self::Context) -> Self::Result {
+        let mut players: Vec<PlayerSummary> = self
+            .connections
+            .keys()
+            .filter_map(|api_key| {
+                let player_id = *self.api_key_to_player_id.get(api_key)?;
+
+                Some(PlayerSummary {
+                    player_id,
+                    team_name: self.team_names.get(&player_id).cloned().unwrap_or_default(),
+                    key_id: api_key.clone(),
+                    frames_behind: self.frames_behind.get(api_key).cloned().unwrap_or(0),
+                })
+            })
+            .collect();
+        players.sort_by_key(|player| player.player_id);
+
+        MessageResult(players)
+    }
+}
+
+impl Handler<CloseRoom> for GameActor {
     type Result = ();
 
-    fn handle(&mut self, msg: GameState, _ctx: &mut Self::Context) {
+    fn handle(&mut self, _msg: CloseRoom, ctx: &mut Self::Context) {
         for addr in self.connections.values().chain(self.spectators.iter()) {
-            addr.do_send(ServerToClient::GameState(msg.clone()));
+            addr.do_send(ClientStop {});
+        }
+
+        self.stop_game_loop();
+
+        HEALTH.unregister(&self.room);
+        METRICS.set_connections(&self.room, None);
+        ctx.stop();
+    }
+}
+
+impl Handler<RedirectPlayers> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: RedirectPlayers, _ctx: &mut Self::Context) {
+        if msg.room == self.room {
+            return;
         }
+
+        for key_id in &msg.key_ids {
+            if let Some(addr) = self.connections.get(key_id) {
+                addr.do_send(Redirect { room: msg.room.clone() });
+            }
+        }
+    }
+}
+
+impl Handler<TournamentUpdate> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: TournamentUpdate, _ctx: &mut Self::Context) {
+        self.record(&ServerToClient::Tournament(msg.tournament.clone()));
+
+        for addr in &self.spectators {
+            addr.do_send(ServerToClient::Tournament(msg.tournament.clone()));
+        }
+
+        self.tournament = Some(msg.tournament);
+    }
+}
+
+impl Handler<GetStandings> for GameActor {
+    type Result = MessageResult<GetStandings>;
+
+    fn handle(&mut self, _msg: GetStandings, _ctx: &mut Self::Context) -> Self::Result {
+        // The scoreboard is from the last checkpoint, at most a second old
+        let scoreboard = self.checkpoint.as_ref().map(|(game_state, _)| &game_state.scoreboard);
+        let stats = &self.stats;
+
+        MessageResult(
+            self.api_key_to_player_id
+                .iter()
+                .map(|(api_key, player_id)| {
+                    let points = scoreboard.and_then(|s| s.get(player_id)).cloned().unwrap_or(0);
+                    let stats = stats.get(player_id).cloned().unwrap_or_default();
+
+                    RoomResult {
+                        key_id: api_key.clone(),
+                        points,
+                        kills: stats.kills,
+                        deaths: stats.bullet_deaths + stats.collision_deaths,
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Handler<ShutDown> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ShutDown, _ctx: &mut Self::Context) {
+        self.shut_down();
     }
 }
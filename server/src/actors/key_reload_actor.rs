@@ -0,0 +1,41 @@
+use crate::keys::KeyStore;
+use actix::{actors::signal, Actor, AsyncContext, Context, Handler, SystemService};
+use std::{sync::Arc, time::Duration};
+
+// How soon keys generated or revoked with `tokyo-server keys` take effect
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Keeps the `KeyStore` in sync with the key file, so authenticating doesn't
+/// have to look at the file. It checks for changes on a timer, and reloads
+/// right away on SIGHUP.
+#[derive(Debug)]
+pub struct KeyReloadActor {
+    keys: Arc<KeyStore>,
+}
+
+impl KeyReloadActor {
+    pub fn new(keys: Arc<KeyStore>) -> Self {
+        KeyReloadActor { keys }
+    }
+}
+
+impl Actor for KeyReloadActor {
+    type Context = Context<KeyReloadActor>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(RELOAD_INTERVAL, |act, _| act.keys.reload_if_changed());
+
+        let signals = signal::ProcessSignals::from_registry();
+        signals.do_send(signal::Subscribe(ctx.address().recipient()));
+    }
+}
+
+impl Handler<signal::Signal> for KeyReloadActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: signal::Signal, _ctx: &mut Self::Context) {
+        if let signal::SignalType::Hup = msg.0 {
+            let _ = self.keys.reload();
+        }
+    }
+}
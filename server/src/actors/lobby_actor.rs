@@ -0,0 +1,308 @@
+use crate::{
+    actors::{game_actor::HUMAN_TAG, ClientWsActor},
+    bots::BOT_TAG,
+    keys::{KeyStore, Role},
+    models::messages::{AuthFailed, Credentials, GetStandings, Heartbeat, Queued, Redirect},
+    ratings::{Outcome, RatingStore},
+    rooms::Rooms,
+    tournament::Entrant,
+};
+use actix::{Actor, ActorFuture, Addr, AsyncContext, Context, Handler, Message, WrapFuture};
+use futures::Future;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The `queue` to connect to `/socket` with for a ranked match.
+pub const RANKED_QUEUE: &str = "ranked";
+
+const MATCHMAKING_INTERVAL: Duration = Duration::from_secs(1);
+const MATCH_LENGTH: Duration = Duration::from_secs(5 * 60);
+
+// Queued sockets which stop answering pings are dropped instead of matched
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(15);
+
+// How far apart the ratings in a match can be, which grows the longer the
+// player who has waited the longest has been queued
+const RATING_SPREAD: f64 = 100.0;
+const RATING_SPREAD_PER_SEC: f64 = 10.0;
+
+#[derive(Debug)]
+struct QueuedPlayer {
+    entrant: Entrant,
+    rating: f64,
+    addr: Addr<ClientWsActor>,
+    queued_at: Instant,
+    last_seen: Instant,
+}
+
+/// Holds players who asked for a ranked match until there are enough of them
+/// with similar ratings, then opens a room for them and redirects their
+/// sockets there. Like tournament matches, ranked matches last a fixed time,
+/// after which the players are rated and the room is closed.
+#[derive(Debug)]
+pub struct LobbyActor {
+    rooms: Rooms,
+    keys: Arc<KeyStore>,
+    ratings: Arc<RatingStore>,
+    match_size: usize,
+    queue: Vec<QueuedPlayer>,
+    // The rooms of the matches being played, and who is playing in them
+    matches: HashMap<String, Vec<Entrant>>,
+    next_match: u32,
+}
+
+impl LobbyActor {
+    pub fn new(
+        rooms: Rooms,
+        keys: Arc<KeyStore>,
+        ratings: Arc<RatingStore>,
+        match_size: usize,
+    ) -> Self {
+        LobbyActor {
+            rooms,
+            keys,
+            ratings,
+            match_size,
+            queue: vec![],
+            matches: HashMap::new(),
+            next_match: 1,
+        }
+    }
+
+    fn join(
+        &mut self,
+        credentials: Credentials,
+        team_name: String,
+        addr: Addr<ClientWsActor>,
+    ) -> Result<(), String> {
+        // Sessions belong to a room, and the queue isn't one
+        let key = match credentials {
+            Credentials::ApiKey(key) => key,
+            _ => return Err("The ranked queue needs an API key".to_string()),
+        };
+
+        if self.rooms.is_replaying() {
+            return Err("This server is replaying a recorded game".to_string());
+        }
+        if key.role == Role::Spectator {
+            return Err("This API key can only spectate".to_string());
+        }
+        if key.role == Role::Human {
+            return Err("Ranked matches are for bots".to_string());
+        }
+
+        let team_name = self.keys.team_name(&key, &team_name)?;
+        if team_name.starts_with(BOT_TAG) || team_name.starts_with(HUMAN_TAG) {
+            return Err(format!("Team names can't start with {} or {}", BOT_TAG, HUMAN_TAG));
+        }
+
+        if self.queue.iter().any(|player| player.entrant.key_id == key.id) {
+            return Err("This API key is already in the queue".to_string());
+        }
+        if self.matches.values().flatten().any(|entrant| entrant.key_id == key.id) {
+            return Err("This API key is already playing a ranked match".to_string());
+        }
+
+        info!("{} joined the ranked queue", team_name);
+        addr.do_send(Queued { key_id: key.id.clone(), team_name: team_name.clone() });
+
+        let now = Instant::now();
+        self.queue.push(QueuedPlayer {
+            entrant: Entrant { key_id: key.id.clone(), team_name },
+            rating: self.ratings.rating(&key.id),
+            addr,
+            queued_at: now,
+            last_seen: now,
+        });
+
+        Ok(())
+    }
+
+    // Starts as many matches as the queue allows, each from the players with
+    // the closest ratings
+    fn find_matches(&mut self, ctx: &mut Context<Self>) {
+        let now = Instant::now();
+        self.queue.retain(|player| now - player.last_seen < CONNECTION_TIMEOUT);
+        self.queue.sort_by(|a, b| a.rating.partial_cmp(&b.rating).unwrap());
+
+        let n = self.match_size;
+        while self.queue.len() >= n {
+            let waiting: Vec<(f64, Duration)> =
+                self.queue.iter().map(|player| (player.rating, now - player.queued_at)).collect();
+            let start = match closest_ratings(&waiting, n) {
+                Some(start) => start,
+                None => break,
+            };
+
+            let room = format!("ranked-{}", self.next_match);
+            let key_ids = self.queue[start..start + n].iter().map(|p| p.entrant.key_id.clone());
+            if let Err(e) = self.rooms.create(&room, Some(key_ids.collect())) {
+                // Most likely there are too many rooms, try again later
+                warn!("Could not open the room for ranked match {}: {}", room, e);
+                break;
+            }
+            self.next_match += 1;
+
+            let players: Vec<QueuedPlayer> = self.queue.drain(start..start + n).collect();
+            for player in &players {
+                player.addr.do_send(Redirect { room: room.clone() });
+            }
+
+            info!("Starting ranked match {} with {} players", room, n);
+            self.matches.insert(room.clone(), players.into_iter().map(|p| p.entrant).collect());
+            ctx.run_later(MATCH_LENGTH, move |act, ctx| act.end_match(room, ctx));
+        }
+    }
+
+    fn end_match(&mut self, room: String, ctx: &mut Context<Self>) {
+        let addr = match self.rooms.get(&room) {
+            Some(addr) => addr,
+            // Closed by hand, so it doesn't count
+            None => {
+                self.matches.remove(&room);
+                return;
+            },
+        };
+
+        let results =
+            addr.send(GetStandings).then(|results| Ok::<_, ()>(results.unwrap_or_default()));
+        let finished = results.into_actor(self).map(move |results, act, _ctx| {
+            let _ = act.rooms.close(&room);
+
+            // Players who never showed up score nothing
+            let outcomes: Vec<Outcome> = act
+                .matches
+                .remove(&room)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|entrant| {
+                    let result = results.iter().find(|result| result.key_id == entrant.key_id);
+                    Outcome {
+                        key_id: entrant.key_id,
+                        team_name: entrant.team_name,
+                        points: result.map_or(0, |result| result.points),
+                        kills: result.map_or(0, |result| result.kills),
+                    }
+                })
+                .collect();
+
+            act.ratings.record(&outcomes);
+            info!("Ranked match {} is over", room);
+        });
+
+        ctx.spawn(finished);
+    }
+}
+
+impl Actor for LobbyActor {
+    type Context = Context<LobbyActor>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(MATCHMAKING_INTERVAL, |act, ctx| act.find_matches(ctx));
+    }
+}
+
+#[derive(Debug, Message)]
+pub enum QueueEvent {
+    Join(Credentials, String, Addr<ClientWsActor>),
+    Leave(String, Addr<ClientWsActor>),
+}
+
+impl Handler<QueueEvent> for LobbyActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: QueueEvent, _ctx: &mut Self::Context) {
+        match msg {
+            QueueEvent::Join(credentials, team_name, addr) => {
+                if let Err(reason) = self.join(credentials, team_name, addr.clone()) {
+                    addr.do_send(AuthFailed { reason });
+                }
+            },
+            QueueEvent::Leave(key_id, addr) => {
+                // Redirected sockets were already taken out of the queue
+                self.queue.retain(|player| player.entrant.key_id != key_id || player.addr != addr);
+            },
+        }
+    }
+}
+
+impl Handler<Heartbeat> for LobbyActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Heartbeat, _ctx: &mut Self::Context) {
+        if let Some(player) = self.queue.iter_mut().find(|p| p.entrant.key_id == msg.api_key) {
+            player.last_seen = Instant::now();
+        }
+    }
+}
+
+// Given the ratings of the queued players in ascending order and how long
+// each has waited, returns where the `match_size` players with the closest
+// ratings start. None if every group is too far apart for how long its
+// players have waited.
+fn closest_ratings(waiting: &[(f64, Duration)], match_size: usize) -> Option<usize> {
+    waiting
+        .windows(match_size)
+        .enumerate()
+        .filter_map(|(start, players)| {
+            let spread = players[match_size - 1].0 - players[0].0;
+            let waited = players.iter().map(|(_, waited)| *waited).max()?;
+            let allowed = RATING_SPREAD + RATING_SPREAD_PER_SEC * waited.as_secs() as f64;
+
+            if spread <= allowed {
+                Some((start, spread))
+            } else {
+                None
+            }
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(start, _)| start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(ratings: &[f64]) -> Vec<(f64, Duration)> {
+        ratings.iter().map(|rating| (*rating, Duration::from_secs(0))).collect()
+    }
+
+    #[test]
+    fn closest_ratings_are_matched_first() {
+        assert_eq!(closest_ratings(&queue(&[1000.0, 1090.0, 1450.0, 1500.0]), 2), Some(2));
+        assert_eq!(closest_ratings(&queue(&[1000.0, 1010.0, 1080.0, 1200.0]), 3), Some(0));
+        assert_eq!(closest_ratings(&queue(&[1000.0, 1050.0, 1100.0, 1120.0]), 3), Some(1));
+    }
+
+    #[test]
+    fn ties_go_to_the_lowest_ratings() {
+        assert_eq!(closest_ratings(&queue(&[1000.0, 1050.0, 1200.0, 1250.0]), 2), Some(0));
+    }
+
+    #[test]
+    fn nobody_is_matched_too_far_apart() {
+        assert_eq!(closest_ratings(&queue(&[1000.0, 1000.0 + RATING_SPREAD + 1.0]), 2), None);
+        assert_eq!(closest_ratings(&queue(&[1000.0, 1000.0 + RATING_SPREAD]), 2), Some(0));
+    }
+
+    #[test]
+    fn not_enough_players_for_a_match() {
+        assert_eq!(closest_ratings(&queue(&[1000.0]), 2), None);
+        assert_eq!(closest_ratings(&queue(&[]), 2), None);
+    }
+
+    #[test]
+    fn allowed_spread_grows_with_the_longest_wait() {
+        let mut waiting = queue(&[1000.0, 1300.0, 2000.0]);
+        assert_eq!(closest_ratings(&waiting, 2), None);
+
+        // 200 more points after 20 seconds, and one player waiting is enough
+        waiting[1].1 = Duration::from_secs(19);
+        assert_eq!(closest_ratings(&waiting, 2), None);
+        waiting[1].1 = Duration::from_secs(20);
+        assert_eq!(closest_ratings(&waiting, 2), Some(0));
+    }
+}
@@ -1,5 +1,13 @@
 pub mod client_ws_actor;
 pub mod game_actor;
+pub mod key_reload_actor;
+pub mod lobby_actor;
+pub mod shutdown_actor;
+pub mod tournament_actor;
 
 pub use client_ws_actor::ClientWsActor;
 pub use game_actor::GameActor;
+pub use key_reload_actor::KeyReloadActor;
+pub use lobby_actor::LobbyActor;
+pub use shutdown_actor::ShutdownActor;
+pub use tournament_actor::TournamentActor;
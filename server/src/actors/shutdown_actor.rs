@@ -0,0 +1,63 @@
+use crate::{health::HEALTH, models::messages::ShutDown, rooms::Rooms};
+use actix::{
+    actors::signal, Actor, ActorFuture, AsyncContext, Context, Handler, System, SystemService,
+    WrapFuture,
+};
+use futures::{future, Future};
+use std::time::Duration;
+
+// Gives the sockets a moment to tell the clients we're restarting
+const SHUTDOWN_DELAY: Duration = Duration::from_millis(500);
+
+/// Shuts the server down cleanly on SIGTERM and Ctrl-C: every room gets to
+/// tell its players and save its snapshot, and then the actor system is
+/// stopped, once.
+#[derive(Debug)]
+pub struct ShutdownActor {
+    rooms: Rooms,
+    shutting_down: bool,
+}
+
+impl ShutdownActor {
+    pub fn new(rooms: Rooms) -> Self {
+        ShutdownActor { rooms, shutting_down: false }
+    }
+
+    fn shut_down(&mut self, ctx: &mut Context<Self>) {
+        // A second Ctrl-C doesn't start over
+        if self.shutting_down {
+            return;
+        }
+        self.shutting_down = true;
+
+        info!("Shutting down the server");
+        HEALTH.set_shutting_down();
+
+        let rooms = self.rooms.all().into_iter().map(|addr| addr.send(ShutDown).then(|_| Ok(())));
+        let stop = future::join_all(rooms).map_err(|()| ()).into_actor(self).map(|_, _, ctx| {
+            ctx.run_later(SHUTDOWN_DELAY, |_, _| System::current().stop());
+        });
+
+        ctx.spawn(stop);
+    }
+}
+
+impl Actor for ShutdownActor {
+    type Context = Context<ShutdownActor>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let signals = signal::ProcessSignals::from_registry();
+        signals.do_send(signal::Subscribe(ctx.address().recipient()));
+    }
+}
+
+impl Handler<signal::Signal> for ShutdownActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: signal::Signal, ctx: &mut Self::Context) {
+        match msg.0 {
+            signal::SignalType::Term | signal::SignalType::Int => self.shut_down(ctx),
+            _ => {},
+        }
+    }
+}
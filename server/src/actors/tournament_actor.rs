@@ -0,0 +1,233 @@
+use crate::{
+    keys::{ApiKey, KeyStore, Role},
+    models::messages::{
+        CancelTournament, CreateTournament, GetStandings, GetTournaments, RedirectPlayers,
+        TournamentUpdate,
+    },
+    ratings::RatingStore,
+    rooms::Rooms,
+    tournament::{self, Entrant, RoomResult, TournamentRun},
+};
+use actix::{Actor, ActorFuture, AsyncContext, Context, Handler, MessageResult, WrapFuture};
+use futures::{future, Future};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokyo::models::Tournament;
+
+/// Runs tournaments, one at a time: it opens a room for every match of a
+/// round, and when the round's time is up, collects the results from the
+/// rooms, rates the players and closes the rooms again.
+pub struct TournamentActor {
+    rooms: Rooms,
+    keys: Arc<KeyStore>,
+    ratings: Arc<RatingStore>,
+    tournaments: Vec<TournamentRun>,
+    tournament_file: Option<String>,
+}
+
+impl TournamentActor {
+    pub fn new(
+        rooms: Rooms,
+        keys: Arc<KeyStore>,
+        ratings: Arc<RatingStore>,
+        tournament_file: Option<String>,
+    ) -> Self {
+        let mut tournaments = match &tournament_file {
+            Some(path) => tournament::load(path).unwrap_or_else(|e| {
+                error!("Could not load the tournament file {}: {}", path, e);
+                vec![]
+            }),
+            None => vec![],
+        };
+
+        // Their rooms are gone after a restart
+        for run in tournaments.iter_mut().filter(|run| run.is_running()) {
+            warn!("Cancelling tournament {}, which was running when the server stopped", run.id());
+            run.cancel();
+        }
+
+        TournamentActor { rooms, keys, ratings, tournaments, tournament_file }
+    }
+
+    fn entrants(&self, key_ids: &[String]) -> Result<Vec<Entrant>, String> {
+        let keys = self.keys.list();
+        let entrant = |key: &ApiKey| Entrant {
+            key_id: key.id.clone(),
+            team_name: key.team_name.clone().unwrap_or_else(|| key.id.clone()),
+        };
+
+        // Without a list, the best rated players get the best seeds
+        if key_ids.is_empty() {
+            let mut keys: Vec<&ApiKey> =
+                keys.iter().filter(|key| key.role == Role::Player).collect();
+            let ratings = &self.ratings;
+            keys.sort_by(|a, b| ratings.rating(&b.id).partial_cmp(&ratings.rating(&a.id)).unwrap());
+
+            return Ok(keys.into_iter().map(entrant).collect());
+        }
+
+        key_ids
+            .iter()
+            .map(|id| match keys.iter().find(|key| &key.id == id) {
+                Some(key) => Ok(entrant(key)),
+                None => Err(format!("There is no API key with the ID {}", id)),
+            })
+            .collect()
+    }
+
+    fn run_mut(&mut self, id: u32) -> Option<&mut TournamentRun> {
+        self.tournaments.iter_mut().find(|run| run.id() == id)
+    }
+
+    fn start_round(&mut self, id: u32, ctx: &mut Context<Self>) {
+        let rooms = &self.rooms;
+        let run = match self.tournaments.iter_mut().find(|run| run.id() == id) {
+            Some(run) if run.is_running() => run,
+            _ => return,
+        };
+
+        for (room, key_ids) in run.start_round(SystemTime::now()) {
+            if let Err(e) = rooms.create(&room, Some(key_ids.iter().cloned().collect())) {
+                error!("Could not open the room for tournament match {}: {}", room, e);
+                continue;
+            }
+
+            // Players are most likely waiting in some other room
+            let redirect = RedirectPlayers { room, key_ids };
+            for addr in rooms.all() {
+                addr.do_send(redirect.clone());
+            }
+        }
+
+        let round_length = Duration::from_secs(run.tournament.round_secs);
+        ctx.run_later(round_length, move |act, ctx| act.end_round(id, ctx));
+
+        self.update(id);
+    }
+
+    fn end_round(&mut self, id: u32, ctx: &mut Context<Self>) {
+        let rooms = match self.run_mut(id) {
+            Some(run) if run.is_running() => run.playing_rooms(),
+            _ => return,
+        };
+
+        // A room which was closed by hand counts as nobody showing up
+        let requests: Vec<_> = rooms
+            .into_iter()
+            .map(|room| {
+                let results: Box<dyn Future<Item = Vec<RoomResult>, Error = ()>> =
+                    match self.rooms.get(&room) {
+                        Some(addr) => Box::new(addr.send(GetStandings).map_err(|_| ())),
+                        None => Box::new(future::ok(vec![])),
+                    };
+
+                results.then(move |results| Ok::<_, ()>((room, results.unwrap_or_default())))
+            })
+            .collect();
+
+        let finished = future::join_all(requests).into_actor(self).map(move |results, act, ctx| {
+            for (room, _) in &results {
+                let _ = act.rooms.close(room);
+            }
+
+            let ratings = &act.ratings;
+            let more_rounds = match act.tournaments.iter_mut().find(|run| run.id() == id) {
+                Some(run) if run.is_running() => {
+                    let round = run.tournament.current_round;
+                    let more_rounds = run.finish_round(results.into_iter().collect());
+
+                    for outcomes in run.outcomes(round) {
+                        ratings.record(&outcomes);
+                    }
+
+                    more_rounds
+                },
+                _ => return,
+            };
+
+            if more_rounds {
+                act.start_round(id, ctx);
+            } else {
+                info!("Tournament {} is over", id);
+                act.update(id);
+            }
+        });
+
+        ctx.spawn(finished);
+    }
+
+    // Shows the new bracket to spectators in every room, and saves it
+    fn update(&mut self, id: u32) {
+        if let Some(run) = self.tournaments.iter().find(|run| run.id() == id) {
+            for addr in self.rooms.all() {
+                addr.do_send(TournamentUpdate { tournament: run.tournament.clone() });
+            }
+        }
+
+        if let Some(path) = &self.tournament_file {
+            if let Err(e) = tournament::save(path, &self.tournaments) {
+                error!("Could not save the tournament file {}: {}", path, e);
+            }
+        }
+    }
+}
+
+impl Actor for TournamentActor {
+    type Context = Context<TournamentActor>;
+}
+
+impl Handler<CreateTournament> for TournamentActor {
+    type Result = Result<Tournament, String>;
+
+    fn handle(&mut self, msg: CreateTournament, ctx: &mut Self::Context) -> Self::Result {
+        if self.rooms.is_replaying() {
+            return Err("This server is replaying a recorded game".to_string());
+        }
+        if self.tournaments.iter().any(TournamentRun::is_running) {
+            return Err("Another tournament is still running".to_string());
+        }
+
+        let id = self.tournaments.iter().map(TournamentRun::id).max().map_or(1, |id| id + 1);
+        let entrants = self.entrants(&msg.config.keys)?;
+        let run = TournamentRun::new(id, &msg.config, entrants)?;
+
+        info!("Starting tournament {} with {} players", id, run.tournament.entrants.len());
+        self.tournaments.push(run);
+        self.start_round(id, ctx);
+
+        Ok(self.tournaments.last().unwrap().tournament.clone())
+    }
+}
+
+impl Handler<CancelTournament> for TournamentActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: CancelTournament, _ctx: &mut Self::Context) -> Self::Result {
+        let rooms = match self.run_mut(msg.id) {
+            Some(run) if run.is_running() => {
+                let rooms = run.playing_rooms();
+                run.cancel();
+                rooms
+            },
+            _ => return false,
+        };
+
+        for room in rooms {
+            let _ = self.rooms.close(&room);
+        }
+
+        info!("Cancelled tournament {}", msg.id);
+        self.update(msg.id);
+        true
+    }
+}
+
+impl Handler<GetTournaments> for TournamentActor {
+    type Result = MessageResult<GetTournaments>;
+
+    fn handle(&mut self, _msg: GetTournaments, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.tournaments.iter().map(|run| run.tournament.clone()).collect())
+    }
+}
@@ -0,0 +1,161 @@
+use rand::{rngs::ThreadRng, Rng};
+use tokyo::{
+    bots::{Chaser, Jakebot, Simple},
+    models::*,
+    Handler,
+};
+
+/// Prefixed to the team names of bots hosted by the server.
+pub const BOT_TAG: &str = "[BOT]";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BotKind {
+    Simple,
+    Chaser,
+    Jakebot,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    // Websocket clients can't send commands more often than every
+    // MIN_COMMAND_INTERVAL, which hard bots get closest to
+    fn ticks_between_commands(self) -> u32 {
+        match self {
+            Difficulty::Easy => 6,
+            Difficulty::Normal => 3,
+            Difficulty::Hard => 2,
+        }
+    }
+
+    // How far off a rotation can be, in radians
+    fn aim_error(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.35,
+            Difficulty::Normal => 0.1,
+            Difficulty::Hard => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BotConfig {
+    pub kind: BotKind,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+}
+
+impl BotConfig {
+    pub fn team_name(&self, player_id: u32) -> String {
+        let kind = serde_json::to_value(self.kind).unwrap();
+        format!("{} {} {}", BOT_TAG, kind.as_str().unwrap_or("bot"), player_id)
+    }
+}
+
+/// A `Handler` run by the game loop, which sees the game state directly
+/// instead of through a websocket.
+pub struct Bot {
+    handler: Box<dyn Handler>,
+    difficulty: Difficulty,
+    cooldown: u32,
+    rejected: Option<Rejection>,
+    rng: ThreadRng,
+}
+
+impl Bot {
+    pub fn new(config: BotConfig) -> Bot {
+        let handler: Box<dyn Handler> = match config.kind {
+            BotKind::Simple => Box::new(Simple::default()),
+            BotKind::Chaser => Box::new(Chaser::default()),
+            BotKind::Jakebot => Box::new(Jakebot::default()),
+        };
+
+        Bot {
+            handler,
+            difficulty: config.difficulty,
+            cooldown: 0,
+            rejected: None,
+            rng: rand::thread_rng(),
+        }
+    }
+
+    /// Called every tick while the bot is alive, with the state's `id` set to
+    /// the bot's player ID.
+    pub fn tick(&mut self, state: &mut ClientState) -> Option<GameCommand> {
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+            return None;
+        }
+
+        self.cooldown = self.difficulty.ticks_between_commands() - 1;
+
+        // Rejections are only reported once, like for websocket clients
+        state.rejected = self.rejected.take();
+
+        let error = self.difficulty.aim_error();
+        match self.handler.tick(state)? {
+            // Hard bots aim perfectly
+            GameCommand::Rotate(angle) if error > 0.0 => {
+                Some(GameCommand::Rotate(angle + self.rng.gen_range(-error, error)))
+            },
+            cmd => Some(cmd),
+        }
+    }
+
+    pub fn rejected(&mut self, rejection: Rejection) {
+        self.rejected = Some(rejection);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_state() -> ClientState {
+        ClientState { id: 1, game_state: GameState::default(), rejected: None, chat: vec![] }
+    }
+
+    fn rotations(commands: impl Iterator<Item = Option<GameCommand>>) -> Vec<f32> {
+        commands
+            .filter_map(|command| match command {
+                Some(GameCommand::Rotate(angle)) => Some(angle),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn bot_rotations(difficulty: Difficulty) -> Vec<f32> {
+        let mut bot = Bot::new(BotConfig { kind: BotKind::Simple, difficulty });
+        let mut state = client_state();
+        rotations((0..60).map(|_| bot.tick(&mut state)))
+    }
+
+    #[test]
+    fn hard_bots_aim_exactly() {
+        let mut simple = Simple::default();
+        let state = client_state();
+        let expected = rotations((0..30).map(|_| simple.tick(&state)));
+
+        assert_eq!(bot_rotations(Difficulty::Hard), expected);
+    }
+
+    #[test]
+    fn easy_bots_miss_by_at_most_their_aim_error() {
+        let angles = bot_rotations(Difficulty::Easy);
+        assert!(!angles.is_empty());
+
+        // Simple turns by 0.01 every tick, and rotates every third command
+        for (i, angle) in angles.iter().enumerate() {
+            let target = 0.01 * (2 + 3 * i) as f32;
+            assert!((angle - target).abs() <= Difficulty::Easy.aim_error() + 1e-4);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::game::GameConfig;
+use std::iter;
+use tokyo::models::Rejection;
+
+/// Tidies up what a player wants to say: control characters like newlines
+/// are dropped, and filtered words are starred out. Returns None when there's
+/// nothing left to say.
+pub fn clean(text: &str, config: &GameConfig) -> Result<Option<String>, Rejection> {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    let text = text.trim();
+
+    if text.chars().count() > config.chat_max_length {
+        return Err(Rejection::MessageTooLong);
+    }
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(filter(text, &config.chat_filter)))
+}
+
+// Only whole words are matched, so filtering a word doesn't mangle the
+// longer words it's part of
+fn filter(text: &str, blocked: &[String]) -> String {
+    let mut filtered = String::with_capacity(text.len());
+    let mut word_start = None;
+
+    // A trailing space ends the last word
+    for (i, c) in text.char_indices().chain(iter::once((text.len(), ' '))) {
+        if c.is_alphanumeric() {
+            word_start.get_or_insert(i);
+            continue;
+        }
+
+        if let Some(start) = word_start.take() {
+            let word = &text[start..i];
+            if blocked.iter().any(|blocked| blocked.trim().to_lowercase() == word.to_lowercase()) {
+                filtered.extend(word.chars().map(|_| '*'));
+            } else {
+                filtered.push_str(word);
+            }
+        }
+
+        if i < text.len() {
+            filtered.push(c);
+        }
+    }
+
+    filtered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(chat_max_length: usize, chat_filter: &[&str]) -> GameConfig {
+        GameConfig {
+            chat_max_length,
+            chat_filter: chat_filter.iter().map(|word| word.to_string()).collect(),
+            ..GameConfig::default()
+        }
+    }
+
+    #[test]
+    fn control_characters_are_dropped() {
+        let config = config(140, &[]);
+        assert_eq!(clean("gg\n\twp\u{7}", &config), Ok(Some("ggwp".to_string())));
+        assert_eq!(clean("  hi there\r\n", &config), Ok(Some("hi there".to_string())));
+        assert_eq!(clean("\n\u{0}\t", &config), Ok(None));
+        assert_eq!(clean("   ", &config), Ok(None));
+    }
+
+    #[test]
+    fn length_is_counted_in_characters() {
+        let config = config(5, &[]);
+
+        // Five characters, but far more than five bytes
+        assert_eq!(clean("日本語🦀é", &config), Ok(Some("日本語🦀é".to_string())));
+        assert_eq!(clean("日本語🦀éé", &config), Err(Rejection::MessageTooLong));
+
+        // Only what's left after cleaning up counts
+        assert_eq!(clean("  🦀🦀🦀🦀🦀\n ", &config), Ok(Some("🦀🦀🦀🦀🦀".to_string())));
+    }
+
+    #[test]
+    fn filtered_words_ignore_case() {
+        let config = config(140, &["heck", " Darn "]);
+        assert_eq!(clean("HECK, darn it", &config), Ok(Some("****, **** it".to_string())));
+        assert_eq!(clean("Heck!heck", &config), Ok(Some("****!****".to_string())));
+    }
+
+    #[test]
+    fn filtered_words_inside_longer_words_are_left_alone() {
+        let config = config(140, &["ass"]);
+        assert_eq!(
+            clean("a classic assassin pass, ass", &config),
+            Ok(Some("a classic assassin pass, ***".to_string()))
+        );
+    }
+
+    #[test]
+    fn filtered_words_keep_their_length_in_characters() {
+        let config = config(140, &["zürich"]);
+        assert_eq!(clean("ZÜRICH über alles", &config), Ok(Some("****** über alles".to_string())));
+    }
+}
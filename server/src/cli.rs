@@ -0,0 +1,238 @@
+use crate::{
+    game::{Game, GameConfig, TICKS_PER_SECOND},
+    keys::{KeyFile, Role},
+    map::Map,
+    recording::Recorder,
+};
+use failure::{format_err, Error};
+use rand::Rng;
+use spin_sleep::LoopHelper;
+use std::{f32::consts::PI, path::PathBuf, time::Duration};
+use structopt::StructOpt;
+use tokyo::models::{GameCommand, ServerToClient};
+
+// How often each simulated player changes course or fires, per tick
+const SIMULATED_ROTATE_CHANCE: f64 = 0.1;
+const SIMULATED_THROTTLE_CHANCE: f64 = 0.05;
+const SIMULATED_FIRE_CHANCE: f64 = 0.1;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "tokyo-server")]
+pub struct Opt {
+    /// Defaults to `serve`
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Run the game server
+    #[structopt(name = "serve")]
+    Serve {
+        #[structopt(flatten)]
+        http: HttpOpt,
+        /// Record the game for `replay`
+        #[structopt(long = "record", parse(from_os_str))]
+        record: Option<PathBuf>,
+    },
+    /// Run a game between randomly acting players, without a server
+    #[structopt(name = "simulate")]
+    Simulate {
+        #[structopt(flatten)]
+        config: ConfigOpt,
+        #[structopt(long = "players", default_value = "8")]
+        players: u32,
+        /// The game's timers use the clock, so this runs in real time
+        #[structopt(long = "seconds", default_value = "60")]
+        seconds: u64,
+        /// Record the game for `replay`
+        #[structopt(long = "record", parse(from_os_str))]
+        record: Option<PathBuf>,
+    },
+    /// Show a recorded game to spectators
+    #[structopt(name = "replay")]
+    Replay {
+        #[structopt(flatten)]
+        http: HttpOpt,
+        #[structopt(parse(from_os_str))]
+        recording: PathBuf,
+    },
+    /// Manage the API keys players connect with
+    #[structopt(name = "keys")]
+    Keys {
+        #[structopt(flatten)]
+        config: ConfigOpt,
+        /// Defaults to the KEY_FILE from the config, or keys.json
+        #[structopt(long = "file", parse(from_os_str))]
+        file: Option<PathBuf>,
+        #[structopt(subcommand)]
+        cmd: KeysCommand,
+    },
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Command::Serve { http: HttpOpt::default(), record: None }
+    }
+}
+
+#[derive(Debug, Default, StructOpt)]
+pub struct ConfigOpt {
+    /// A .env style file with the config variables, instead of the .env in
+    /// the working directory
+    #[structopt(long = "config", short = "c", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct HttpOpt {
+    #[structopt(flatten)]
+    pub config: ConfigOpt,
+    /// The address to listen on, defaults to 0.0.0.0 and the SERVER_PORT from
+    /// the config
+    #[structopt(long = "bind")]
+    pub bind: Option<String>,
+    /// Where the spectator page is served from
+    #[structopt(long = "static-dir", default_value = "../spectator", parse(from_os_str))]
+    pub static_dir: PathBuf,
+}
+
+impl Default for HttpOpt {
+    fn default() -> Self {
+        HttpOpt { config: ConfigOpt::default(), bind: None, static_dir: "../spectator".into() }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum KeysCommand {
+    /// Create a new key and print it, it can't be recovered afterwards
+    #[structopt(name = "generate")]
+    Generate {
+        /// Lock the key to this team name
+        #[structopt(long = "team")]
+        team_name: Option<String>,
+        /// player, human, admin or spectator
+        #[structopt(long = "role", default_value = "player")]
+        role: Role,
+        /// Only allow the key into these rooms, can be repeated
+        #[structopt(long = "room")]
+        rooms: Vec<String>,
+        /// Commands per second, instead of the server default
+        #[structopt(long = "rate-limit")]
+        rate_limit: Option<u32>,
+        /// Make the key stop working after this many days
+        #[structopt(long = "expires-in-days")]
+        expires_in_days: Option<u64>,
+    },
+    /// Remove a key by its ID
+    #[structopt(name = "revoke")]
+    Revoke { id: String },
+    /// Show the keys and their metadata, but not the keys themselves
+    #[structopt(name = "list")]
+    List,
+}
+
+pub fn simulate(
+    map: Map,
+    game_config: GameConfig,
+    players: u32,
+    seconds: u64,
+    mut recorder: Option<Recorder>,
+) -> Result<(), Error> {
+    let mut loop_helper = LoopHelper::builder().build_with_target_rate(TICKS_PER_SECOND);
+    let mut rng = rand::thread_rng();
+    let mut game = Game::new(map, game_config);
+
+    for player_id in 0..players {
+        game.add_player(player_id);
+    }
+
+    if let Some(recorder) = &mut recorder {
+        let team_names = (0..players).map(|id| (id, format!("simulated {}", id))).collect();
+        recorder.write(&ServerToClient::TeamNames(team_names));
+    }
+
+    let ticks = seconds * TICKS_PER_SECOND as u64;
+    for tick in 1..=ticks {
+        loop_helper.loop_start();
+
+        for player_id in 0..players {
+            if rng.gen_bool(SIMULATED_ROTATE_CHANCE) {
+                let _ =
+                    game.handle_cmd(player_id, GameCommand::Rotate(rng.gen_range(0.0, 2.0 * PI)));
+            }
+            if rng.gen_bool(SIMULATED_THROTTLE_CHANCE) {
+                let _ = game.handle_cmd(player_id, GameCommand::Throttle(rng.gen()));
+            }
+            if rng.gen_bool(SIMULATED_FIRE_CHANCE) {
+                let _ = game.handle_cmd(player_id, GameCommand::Fire);
+            }
+        }
+
+        game.tick(1.0 / TICKS_PER_SECOND);
+
+        if let Some(recorder) = &mut recorder {
+            recorder.write(&ServerToClient::GameState(game.state.clone()));
+
+            if tick % TICKS_PER_SECOND as u64 == 0 {
+                recorder.write(&ServerToClient::Stats(game.stats()));
+            }
+        }
+
+        loop_helper.loop_sleep();
+    }
+
+    if let Some(recorder) = &mut recorder {
+        recorder.flush();
+    }
+
+    println!("{}", serde_json::to_string_pretty(&game.state.scoreboard)?);
+    println!("{}", serde_json::to_string_pretty(&game.stats())?);
+
+    Ok(())
+}
+
+pub fn keys(file: PathBuf, cmd: KeysCommand) -> Result<(), Error> {
+    let mut key_file = KeyFile::load(&file)
+        .map_err(|e| format_err!("Could not load the key file {}: {}", file.display(), e))?;
+
+    match cmd {
+        KeysCommand::Generate { team_name, role, rooms, rate_limit, expires_in_days } => {
+            let expires_in = expires_in_days.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+            let (key, api_key) =
+                key_file.generate(team_name, role, rooms, rate_limit, expires_in)?;
+
+            println!("Generated key {} ({:?}):", api_key.id, api_key.role);
+            println!("{}", key);
+        },
+        KeysCommand::Revoke { id } => {
+            if key_file.revoke(&id).is_none() {
+                return Err(format_err!("There is no key with the ID {}", id));
+            }
+
+            println!("Revoked key {}", id);
+        },
+        KeysCommand::List => {
+            for key in &key_file.keys {
+                let rooms = if key.rooms.is_empty() { "all".into() } else { key.rooms.join(",") };
+
+                println!(
+                    "{}  role={:?} team={} rooms={} rate_limit={} expires={}{}",
+                    key.id,
+                    key.role,
+                    key.team_name.as_deref().unwrap_or("-"),
+                    rooms,
+                    key.rate_limit.map_or("-".into(), |rate_limit| rate_limit.to_string()),
+                    key.expires.map_or("-".into(), |expires| expires.to_string()),
+                    if key.is_expired() { " (expired)" } else { "" },
+                );
+            }
+
+            return Ok(());
+        },
+    }
+
+    key_file
+        .save(&file)
+        .map_err(|e| format_err!("Could not save the key file {}: {}", file.display(), e))
+}
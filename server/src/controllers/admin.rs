@@ -0,0 +1,200 @@
+use crate::{
+    actors::GameActor,
+    bots::BotConfig,
+    controllers::api::bearer_key,
+    keys::Role,
+    models::messages::{
+        AddBot, CancelTournament, CreateTournament, ListBots, ListPlayers, RemoveBot,
+    },
+    tournament::TournamentConfig,
+    AppState,
+};
+use actix::Addr;
+use actix_web::{AsyncResponder, FutureResponse, HttpRequest, HttpResponse, Json, Path};
+use futures::{future, Future};
+
+// Everything under /admin needs an admin key in the Authorization header
+fn authorize(req: &HttpRequest<AppState>) -> Result<(), HttpResponse> {
+    match bearer_key(req).and_then(|key| req.state().keys.authenticate(&key)) {
+        Some(key) if key.role == Role::Admin => Ok(()),
+        Some(_) => Err(HttpResponse::Forbidden().body("This API key isn't an admin key")),
+        None => Err(HttpResponse::Unauthorized().body("Invalid API Key")),
+    }
+}
+
+fn room_addr(req: &HttpRequest<AppState>, room: &str) -> Result<Addr<GameActor>, HttpResponse> {
+    authorize(req)?;
+
+    req.state()
+        .rooms
+        .get(room)
+        .ok_or_else(|| HttpResponse::NotFound().body(format!("There is no room {}", room)))
+}
+
+pub fn rooms_handler(req: HttpRequest<AppState>) -> HttpResponse {
+    match authorize(&req) {
+        Ok(()) => HttpResponse::Ok().json(req.state().rooms.names()),
+        Err(response) => response,
+    }
+}
+
+pub fn create_room_handler((req, path): (HttpRequest<AppState>, Path<(String,)>)) -> HttpResponse {
+    if let Err(response) = authorize(&req) {
+        return response;
+    }
+
+    match req.state().rooms.create(&path.0, None) {
+        Ok(_) => HttpResponse::Created().finish(),
+        Err(reason) => HttpResponse::Conflict().body(reason),
+    }
+}
+
+pub fn close_room_handler((req, path): (HttpRequest<AppState>, Path<(String,)>)) -> HttpResponse {
+    if let Err(response) = authorize(&req) {
+        return response;
+    }
+
+    match req.state().rooms.close(&path.0) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(reason) => HttpResponse::BadRequest().body(reason),
+    }
+}
+
+/// Picks up keys generated or revoked since the key file was last loaded,
+/// without waiting for the next periodic check.
+pub fn reload_keys_handler(req: HttpRequest<AppState>) -> HttpResponse {
+    if let Err(response) = authorize(&req) {
+        return response;
+    }
+
+    match req.state().keys.reload() {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Could not reload: {}", e)),
+    }
+}
+
+pub fn bots_handler(
+    (req, path): (HttpRequest<AppState>, Path<(String,)>),
+) -> FutureResponse<HttpResponse> {
+    let game_addr = match room_addr(&req, &path.0) {
+        Ok(game_addr) => game_addr,
+        Err(response) => return Box::new(future::ok(response)),
+    };
+
+    game_addr.send(ListBots).from_err().map(|bots| HttpResponse::Ok().json(bots)).responder()
+}
+
+/// Everybody connected to the room, with how many state frames their
+/// sockets are behind.
+pub fn players_handler(
+    (req, path): (HttpRequest<AppState>, Path<(String,)>),
+) -> FutureResponse<HttpResponse> {
+    let game_addr = match room_addr(&req, &path.0) {
+        Ok(game_addr) => game_addr,
+        Err(response) => return Box::new(future::ok(response)),
+    };
+
+    game_addr
+        .send(ListPlayers)
+        .from_err()
+        .map(|players| HttpResponse::Ok().json(players))
+        .responder()
+}
+
+/// Takes a body like `{"kind": "chaser", "difficulty": "hard"}`, where
+/// `difficulty` defaults to normal.
+pub fn add_bot_handler(
+    (req, path, config): (HttpRequest<AppState>, Path<(String,)>, Json<BotConfig>),
+) -> FutureResponse<HttpResponse> {
+    let game_addr = match room_addr(&req, &path.0) {
+        Ok(game_addr) => game_addr,
+        Err(response) => return Box::new(future::ok(response)),
+    };
+
+    game_addr
+        .send(AddBot { config: config.into_inner() })
+        .from_err()
+        .map(|bot| HttpResponse::Created().json(bot))
+        .responder()
+}
+
+pub fn remove_bot_handler(
+    (req, path): (HttpRequest<AppState>, Path<(String, u32)>),
+) -> FutureResponse<HttpResponse> {
+    let game_addr = match room_addr(&req, &path.0) {
+        Ok(game_addr) => game_addr,
+        Err(response) => return Box::new(future::ok(response)),
+    };
+
+    game_addr
+        .send(RemoveBot { player_id: path.1 })
+        .from_err()
+        .map(|removed| {
+            if removed {
+                HttpResponse::NoContent().finish()
+            } else {
+                HttpResponse::NotFound().body("There is no bot with that player ID")
+            }
+        })
+        .responder()
+}
+
+/// Takes a body like `{"format": "elimination", "keys": ["<key ID>", ...]}`,
+/// see `TournamentConfig` for the rest.
+pub fn create_tournament_handler(
+    (req, config): (HttpRequest<AppState>, Json<TournamentConfig>),
+) -> FutureResponse<HttpResponse> {
+    if let Err(response) = authorize(&req) {
+        return Box::new(future::ok(response));
+    }
+
+    req.state()
+        .tournaments
+        .send(CreateTournament { config: config.into_inner() })
+        .from_err()
+        .map(|result| match result {
+            Ok(tournament) => HttpResponse::Created().json(tournament),
+            Err(reason) => HttpResponse::BadRequest().body(reason),
+        })
+        .responder()
+}
+
+pub fn cancel_tournament_handler(
+    (req, path): (HttpRequest<AppState>, Path<(u32,)>),
+) -> FutureResponse<HttpResponse> {
+    if let Err(response) = authorize(&req) {
+        return Box::new(future::ok(response));
+    }
+
+    req.state()
+        .tournaments
+        .send(CancelTournament { id: path.0 })
+        .from_err()
+        .map(|cancelled| {
+            if cancelled {
+                HttpResponse::NoContent().finish()
+            } else {
+                HttpResponse::NotFound().body("There is no running tournament with that ID")
+            }
+        })
+        .responder()
+}
+
+pub fn ratings_handler(req: HttpRequest<AppState>) -> HttpResponse {
+    match authorize(&req) {
+        Ok(()) => HttpResponse::Ok().json(req.state().ratings.all()),
+        Err(response) => response,
+    }
+}
+
+pub fn reset_rating_handler((req, path): (HttpRequest<AppState>, Path<(String,)>)) -> HttpResponse {
+    if let Err(response) = authorize(&req) {
+        return response;
+    }
+
+    if req.state().ratings.reset(&path.0) {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().body("That API key hasn't played a rated game")
+    }
+}
@@ -1,35 +1,219 @@
-use crate::{actors::ClientWsActor, AppState};
-use actix_web::{HttpRequest, Query, State};
+use crate::{
+    actors::{
+        client_ws_actor::Destination, game_actor::SESSION_TTL, lobby_actor::RANKED_QUEUE,
+        ClientWsActor, GameActor,
+    },
+    health::HEALTH,
+    metrics::METRICS,
+    models::messages::{CreateSession, Credentials, GetStats, GetTournaments},
+    rooms::MAIN_ROOM,
+    AppState,
+};
+use actix::Addr;
+use actix_web::{
+    http::header, AsyncResponder, FutureResponse, HttpMessage, HttpRequest, HttpResponse, Path,
+    Query, State,
+};
+use futures::{future, Future};
 
 #[derive(Debug, Deserialize)]
 pub struct QueryString {
-    key: String,
     name: String,
+    token: Option<String>,
+    room: Option<String>,
+    queue: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoomQuery {
+    room: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct LeaderboardEntry {
+    team_name: String,
+    rating: i64,
+    games: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct SessionResponse {
+    token: String,
+    expires_in_secs: u64,
+}
+
+// The API key from an `Authorization: Bearer <key>` header
+pub fn bearer_key<S>(req: &HttpRequest<S>) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let mut parts = value.splitn(2, ' ');
+
+    match (parts.next(), parts.next()) {
+        (Some("Bearer"), Some(key)) => Some(key.trim().to_string()),
+        _ => None,
+    }
+}
+
+// The game actor of the requested room, or of the main room
+fn room_addr(state: &AppState, room: &Option<String>) -> Result<Addr<GameActor>, actix_web::Error> {
+    let room = room.as_ref().map(String::as_str).unwrap_or(MAIN_ROOM);
+
+    state
+        .rooms
+        .get(room)
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("There is no room {}", room)))
 }
 
 pub fn socket_handler(
     (req, state, query): (HttpRequest<AppState>, State<AppState>, Query<QueryString>),
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
-    if crate::APP_CONFIG.dev_mode || crate::APP_CONFIG.api_keys.contains(&query.key) {
-        actix_web::ws::start(
-            &req,
-            ClientWsActor::new(state.game_addr.clone(), query.key.clone(), query.name.clone()),
-        )
-    } else {
-        Err(actix_web::error::ErrorBadRequest("Invalid API Key"))
-    }
+    // The ranked queue picks the room, and sessions only work in the room
+    // they're from
+    let destination = match &query.queue {
+        None => Destination::Room(room_addr(&state, &query.room)?),
+        Some(queue) if queue == RANKED_QUEUE => {
+            if query.room.is_some() || query.token.is_some() {
+                let reason = "The ranked queue can't be joined with a room or a session token";
+                return Err(actix_web::error::ErrorBadRequest(reason));
+            }
+
+            Destination::Queue(state.lobby.clone())
+        },
+        Some(queue) => {
+            return Err(actix_web::error::ErrorNotFound(format!("There is no queue {}", queue)))
+        },
+    };
+
+    // Without a session token or an Authorization header, the client sends
+    // its API key as the first websocket message instead
+    let credentials = match (&query.token, bearer_key(&req)) {
+        (Some(token), key) => {
+            let key = key.and_then(|key| state.keys.authenticate(&key));
+            Some(Credentials::Session(token.clone(), key))
+        },
+        (None, Some(key)) => match state.keys.authenticate(&key) {
+            Some(key) => Some(Credentials::ApiKey(key)),
+            None => return Err(actix_web::error::ErrorUnauthorized("Invalid API Key")),
+        },
+        (None, None) => None,
+    };
+
+    let client = ClientWsActor::new(destination, credentials, query.name.clone());
+    actix_web::ws::start(&req, client)
+}
+
+/// Sessions belong to a room, so the token only works with the same `room`.
+pub fn session_handler(
+    (req, query): (HttpRequest<AppState>, Query<RoomQuery>),
+) -> FutureResponse<HttpResponse> {
+    let key = match bearer_key(&req).and_then(|key| req.state().keys.authenticate(&key)) {
+        Some(key) => key,
+        None => return Box::new(future::ok(HttpResponse::Unauthorized().body("Invalid API Key"))),
+    };
+
+    let game_addr = match room_addr(req.state(), &query.room) {
+        Ok(game_addr) => game_addr,
+        Err(e) => return Box::new(future::err(e)),
+    };
+
+    game_addr
+        .send(CreateSession { key })
+        .from_err()
+        .map(|token| {
+            HttpResponse::Ok()
+                .json(SessionResponse { token, expires_in_secs: SESSION_TTL.as_secs() })
+        })
+        .responder()
 }
 
 pub fn spectate_handler(
-    (req, state): (HttpRequest<AppState>, State<AppState>),
+    (req, state, query): (HttpRequest<AppState>, State<AppState>, Query<RoomQuery>),
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let game_addr = room_addr(&state, &query.room)?;
+
     // TODO(bschwind) - Make a separate spectator actor
     actix_web::ws::start(
         &req,
         ClientWsActor::new(
-            state.game_addr.clone(),
-            "SPECTATOR".to_string(),
+            Destination::Room(game_addr),
+            Some(Credentials::Spectator),
             "SPECTATOR".to_string(),
         ),
     )
 }
+
+pub fn stats_handler(
+    (state, query): (State<AppState>, Query<RoomQuery>),
+) -> FutureResponse<HttpResponse> {
+    let game_addr = match room_addr(&state, &query.room) {
+        Ok(game_addr) => game_addr,
+        Err(e) => return Box::new(future::err(e)),
+    };
+
+    game_addr.send(GetStats).from_err().map(|stats| HttpResponse::Ok().json(stats)).responder()
+}
+
+pub fn tournaments_handler(state: State<AppState>) -> FutureResponse<HttpResponse> {
+    state
+        .tournaments
+        .send(GetTournaments)
+        .from_err()
+        .map(|tournaments| HttpResponse::Ok().json(tournaments))
+        .responder()
+}
+
+pub fn tournament_handler(
+    (state, path): (State<AppState>, Path<(u32,)>),
+) -> FutureResponse<HttpResponse> {
+    let id = path.0;
+
+    state
+        .tournaments
+        .send(GetTournaments)
+        .from_err()
+        .map(move |tournaments| match tournaments.into_iter().find(|t| t.id == id) {
+            Some(tournament) => HttpResponse::Ok().json(tournament),
+            None => HttpResponse::NotFound().body("There is no tournament with that ID"),
+        })
+        .responder()
+}
+
+/// Everybody who has played a rated game, best first. Key IDs stay private.
+pub fn leaderboard_handler(state: State<AppState>) -> HttpResponse {
+    let mut ratings: Vec<_> = state.ratings.all().into_values().collect();
+    ratings.sort_by(|a, b| b.rating.partial_cmp(&a.rating).unwrap());
+
+    let leaderboard: Vec<LeaderboardEntry> = ratings
+        .into_iter()
+        .map(|rating| LeaderboardEntry {
+            team_name: rating.team_name,
+            rating: rating.rating.round() as i64,
+            games: rating.games,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(leaderboard)
+}
+
+pub fn metrics_handler(_req: HttpRequest<AppState>) -> HttpResponse {
+    HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(METRICS.render())
+}
+
+pub fn healthz_handler(_req: HttpRequest<AppState>) -> HttpResponse {
+    let status = HEALTH.status(MAIN_ROOM);
+
+    if status.healthy {
+        HttpResponse::Ok().json(status)
+    } else {
+        HttpResponse::ServiceUnavailable().json(status)
+    }
+}
+
+pub fn readyz_handler(_req: HttpRequest<AppState>) -> HttpResponse {
+    let status = HEALTH.status(MAIN_ROOM);
+
+    if status.ready {
+        HttpResponse::Ok().json(status)
+    } else {
+        HttpResponse::ServiceUnavailable().json(status)
+    }
+}
@@ -2,16 +2,33 @@ use std::time::Instant;
 use std::collections::HashMap;
 use std::time::{Duration, SystemTime};
 use std::collections::HashSet;
-use tokyo::models::{
-    BulletState, DeadPlayer, GameCommand, GameState, PlayerState, BULLET_RADIUS, BULLET_SPEED,
-    PLAYER_BASE_SPEED, PLAYER_RADIUS,
+use crate::map::Map;
+use rand::{seq::SliceRandom, Rng};
+use tokyo::{
+    models::{
+        ActiveEffect, BulletState, DeadPlayer, GameCommand, GameState, ItemKind, ItemState,
+        Obstacle, PhysicsMode, PlayerState, PlayerStats, PlayerStatus, Rejection, BULLET_RADIUS,
+        BULLET_SPEED, ITEM_RADIUS, PLAYER_RADIUS, SCORE_MULTIPLIER,
+    },
+    physics,
+    spatial::{SpatialGrid, DEFAULT_CELL_SIZE, LINEAR_SCAN_LIMIT},
 };
 
 const DEAD_PUNISH: Duration = Duration::from_secs(1);
 
 pub const TICKS_PER_SECOND: f32 = 30.0;
-const BOUNDS: (f32, f32) = (2880.0, 1920.0);
+pub const BOUNDS: (f32, f32) = (2880.0, 1920.0);
 const MAX_CONCURRENT_BULLETS: usize = 4;
+const RAPID_FIRE_MAX_BULLETS: usize = 8;
+
+const ITEM_KINDS: [ItemKind; 4] =
+    [ItemKind::SpeedBoost, ItemKind::RapidFire, ItemKind::Shield, ItemKind::ScoreMultiplier];
+
+// Give up looking for a spot clear of obstacles after this many tries
+const MAX_PLACEMENT_ATTEMPTS: usize = 32;
+
+// How far ahead to follow bullets when looking for a safe spawn point
+const SPAWN_BULLET_LOOKAHEAD_SECS: f32 = 1.0;
 
 // Time until you start accruing points for surviving
 const SURVIVAL_TIMEOUT: u64 = 10;
@@ -19,13 +36,152 @@ const SURVIVAL_TIMEOUT: u64 = 10;
 // Interval for accruing points after reaching the threshold
 const SURVIVAL_POINT_INTERVAL: u64 = 4;
 
+// Players moving less than this per tick aren't considered to be moving
+const IDLE_DISTANCE: f32 = 1.0;
+
+// Cap on how many times the survival interval doubles for idle players
+const MAX_IDLE_DECAY: u64 = 5;
+
+/// Tunable rules of the game, read from `GAME_` prefixed environment variables.
+/// The optional mechanics are disabled by default, which plays like the
+/// original one-hit-kill game.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    /// Players have health points instead of dying from a single bullet.
+    pub health_enabled: bool,
+    pub max_health: f32,
+    pub bullet_damage: f32,
+    pub health_regen_per_second: f32,
+
+    /// Points for the player who dealt the final blow.
+    pub kill_points: u32,
+
+    /// Points for every other player who damaged the victim within the
+    /// `assist_window_secs` before the kill.
+    pub assist_points: u32,
+    pub assist_window_secs: u64,
+
+    /// Power-ups spawn in the arena, at the map's item spawn points if it has
+    /// any.
+    pub items_enabled: bool,
+    pub item_spawn_interval_secs: u64,
+    pub max_items: usize,
+    pub item_effect_secs: f32,
+
+    /// Ships accelerate and turn gradually instead of moving instantly, see
+    /// `tokyo::physics`.
+    pub momentum_enabled: bool,
+
+    /// Bullets disappear after flying for this long. None to keep them until
+    /// they leave the arena.
+    pub bullet_lifetime_secs: Option<f32>,
+
+    /// How many times bullets ricochet off the arena walls.
+    pub bullet_bounces: u32,
+
+    /// Players have to wait between shots, and reload once their magazine is
+    /// empty.
+    pub weapon_enabled: bool,
+    pub fire_cooldown_secs: f32,
+    pub magazine_size: u32,
+    pub reload_secs: f32,
+
+    /// Players spawn at least this far away from everyone else, if there's
+    /// room for it.
+    pub min_spawn_distance: f32,
+
+    /// Freshly spawned players can't be hurt and don't collide for this long,
+    /// or until they fire. Zero to disable.
+    pub spawn_protection_secs: f32,
+
+    /// Players who lost their connection are removed from the arena after
+    /// this long, unless they reconnect.
+    pub disconnect_grace_secs: u64,
+
+    /// Players who haven't moved or fired for this long are marked as idle.
+    /// Zero to disable.
+    pub idle_timeout_secs: u64,
+
+    /// What idle players get for staying alive.
+    pub idle_survival: IdleSurvival,
+
+    /// Chat messages longer than this many characters are rejected, and
+    /// players have to wait `chat_interval_secs` between messages.
+    pub chat_max_length: usize,
+    pub chat_interval_secs: f32,
+
+    /// Words which get starred out of chat messages, ignoring case. Set as a
+    /// comma separated list.
+    pub chat_filter: Vec<String>,
+}
+
+impl GameConfig {
+    /// Catches settings the game can't be played with.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.magazine_size == 0 {
+            return Err("GAME_MAGAZINE_SIZE has to be at least 1".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            health_enabled: false,
+            max_health: 100.0,
+            bullet_damage: 35.0,
+            health_regen_per_second: 5.0,
+            kill_points: 1,
+            assist_points: 1,
+            assist_window_secs: 10,
+            items_enabled: false,
+            item_spawn_interval_secs: 10,
+            max_items: 4,
+            item_effect_secs: 10.0,
+            momentum_enabled: false,
+            bullet_lifetime_secs: None,
+            bullet_bounces: 0,
+            weapon_enabled: false,
+            fire_cooldown_secs: 0.25,
+            magazine_size: 6,
+            reload_secs: 1.5,
+            min_spawn_distance: 300.0,
+            spawn_protection_secs: 2.0,
+            disconnect_grace_secs: 30,
+            idle_timeout_secs: 30,
+            idle_survival: IdleSurvival::Keep,
+            chat_max_length: 140,
+            chat_interval_secs: 2.0,
+            chat_filter: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum IdleSurvival {
+    /// Idle players earn survival points like everybody else.
+    #[serde(rename = "keep")]
+    Keep,
+
+    /// The time between survival points doubles for every `idle_timeout_secs`
+    /// a player stays idle.
+    #[serde(rename = "decay")]
+    Decay,
+
+    /// Idle players don't earn survival points.
+    #[serde(rename = "none")]
+    None,
+}
+
 pub trait Triangle {
     fn x(&self) -> f32;
     fn y(&self) -> f32;
-    fn angle(&self) -> f32;
     fn radius(&self) -> f32;
 
-    fn is_colliding(&self, other: &Triangle) -> bool {
+    fn is_colliding(&self, other: &dyn Triangle) -> bool {
         let d_x = other.x() - self.x();
         let d_y = other.y() - self.y();
         let d_r = other.radius() + self.radius();
@@ -45,16 +201,12 @@ impl Triangle for PlayerState {
         self.y
     }
 
-    fn angle(&self) -> f32 {
-        self.angle
-    }
-
     fn radius(&self) -> f32 {
         PLAYER_RADIUS
     }
 }
 
-impl Triangle for BulletState {
+impl Triangle for ItemState {
     fn x(&self) -> f32 {
         self.x
     }
@@ -63,8 +215,18 @@ impl Triangle for BulletState {
         self.y
     }
 
-    fn angle(&self) -> f32 {
-        self.angle
+    fn radius(&self) -> f32 {
+        ITEM_RADIUS
+    }
+}
+
+impl Triangle for BulletState {
+    fn x(&self) -> f32 {
+        self.x
+    }
+
+    fn y(&self) -> f32 {
+        self.y
     }
 
     fn radius(&self) -> f32 {
@@ -74,23 +236,77 @@ impl Triangle for BulletState {
 
 pub struct Game {
     pub state: GameState,
+    config: GameConfig,
     rng: rand::rngs::ThreadRng,
     bullet_id_counter: u32,
+    item_id_counter: u32,
+    item_spawns: Vec<(f32, f32)>,
+    spawn_points: Vec<(f32, f32)>,
+    next_item_spawn: Instant,
     survival_times: HashMap<u32, Instant>,
+    // Who damaged whom and when, used to hand out assists
+    damage_log: HashMap<u32, Vec<(u32, Instant)>>,
+    // When players last moved or fired, and when they disconnected
+    last_active: HashMap<u32, Instant>,
+    disconnected: HashMap<u32, Instant>,
+    stats: HashMap<u32, PlayerStats>,
+    life_started: HashMap<u32, Instant>,
+    // Kept around between ticks so its cells don't get reallocated every time
+    player_grid: SpatialGrid<usize>,
+    candidates: Vec<usize>,
 }
 
 impl Default for Game {
     fn default() -> Self {
-        Self { state: GameState::new(BOUNDS), rng: Default::default(), bullet_id_counter: 0, survival_times: HashMap::new() }
+        Self::new(Map::default(), GameConfig::default())
     }
 }
 
 impl Game {
+    pub fn new(map: Map, config: GameConfig) -> Self {
+        let mut state = GameState::new(map.bounds);
+        let player_grid = SpatialGrid::new(map.bounds, DEFAULT_CELL_SIZE);
+        state.obstacles = map.obstacles;
+        state.physics =
+            if config.momentum_enabled { PhysicsMode::Momentum } else { PhysicsMode::Instant };
+
+        Self {
+            next_item_spawn: Instant::now() + Duration::from_secs(config.item_spawn_interval_secs),
+            state,
+            config,
+            rng: Default::default(),
+            bullet_id_counter: 0,
+            item_id_counter: 0,
+            item_spawns: map.item_spawns,
+            spawn_points: map.spawn_points,
+            survival_times: HashMap::new(),
+            damage_log: HashMap::new(),
+            last_active: HashMap::new(),
+            disconnected: HashMap::new(),
+            stats: HashMap::new(),
+            life_started: HashMap::new(),
+            player_grid,
+            candidates: vec![],
+        }
+    }
+
     pub fn add_player(&mut self, player_id: u32) {
-        let mut player = PlayerState::new(player_id);
-        player.randomize(&mut self.rng, BOUNDS);
-        self.state.players.push(player);
-        self.survival_times.insert(player_id, Instant::now() + Duration::from_secs(SURVIVAL_TIMEOUT));
+        self.disconnected.remove(&player_id);
+        self.last_active.insert(player_id, Instant::now());
+        self.survival_times
+            .entry(player_id)
+            .or_insert_with(|| Instant::now() + Duration::from_secs(SURVIVAL_TIMEOUT));
+
+        // Players who reconnected in time pick up where they left off
+        let exists = self.state.players.iter().any(|p| p.id == player_id)
+            || self.state.dead.iter().any(|corpse| corpse.player.id == player_id);
+
+        if !exists {
+            let mut player = PlayerState::new(player_id);
+            reset_player(&mut player, &self.config);
+            self.spawn(&mut player);
+            self.state.players.push(player);
+        }
     }
 
     pub fn player_left(&mut self, player_id: u32) {
@@ -101,23 +317,48 @@ impl Game {
         }
 
         self.survival_times.remove(&player_id);
+        self.disconnected.insert(player_id, Instant::now());
     }
 
-    pub fn handle_cmd(&mut self, player_id: u32, cmd: GameCommand) {
+    pub fn handle_cmd(&mut self, player_id: u32, cmd: GameCommand) -> Result<(), Rejection> {
         // info!("Player {} sent command {:#?}", player_id, cmd);
 
         if let Some(player) = self.state.players.iter_mut().find(|p| p.id == player_id) {
             match cmd {
+                // A NaN would spread to the position and break every geometry
+                // check after it
+                GameCommand::Rotate(value) | GameCommand::Throttle(value) if !value.is_finite() => {
+                    return Err(Rejection::NotANumber);
+                },
                 GameCommand::Rotate(angle) => {
-                    player.angle = angle;
+                    player.target_angle = angle;
+
+                    if self.state.physics == PhysicsMode::Instant {
+                        player.angle = angle;
+                    }
                 },
                 GameCommand::Throttle(throttle) => {
                     // Bound and re-map throttle inputs.
-                    let throttle = throttle.max(0.0).min(1.0);
+                    let throttle = throttle.clamp(0.0, 1.0);
 
                     player.throttle = throttle;
                 },
                 GameCommand::Fire => {
+                    if self.config.weapon_enabled {
+                        if player.ammo == 0 {
+                            return Err(Rejection::Reloading);
+                        }
+                        if player.cooldown > 0.0 {
+                            return Err(Rejection::CoolingDown);
+                        }
+                    }
+
+                    let max_bullets = if player.has_effect(ItemKind::RapidFire) {
+                        RAPID_FIRE_MAX_BULLETS
+                    } else {
+                        MAX_CONCURRENT_BULLETS
+                    };
+
                     let active_bullets = self
                         .state
                         .bullets
@@ -125,134 +366,519 @@ impl Game {
                         .filter(|bullet| bullet.player_id == player.id)
                         .count();
 
-                    if active_bullets < MAX_CONCURRENT_BULLETS {
-                        let bullet_id = self.bullet_id_counter;
-                        self.bullet_id_counter += 1;
+                    if active_bullets >= max_bullets {
+                        return Err(Rejection::TooManyBullets);
+                    }
+
+                    let bullet_id = self.bullet_id_counter;
+                    self.bullet_id_counter += 1;
+
+                    let distance_from_player: f32 = 5.0;
+                    let (bullet_x, bullet_y) = angle_to_vector(player.angle);
 
-                        let distance_from_player: f32 = 5.0;
-                        let (bullet_x, bullet_y) = angle_to_vector(player.angle);
+                    self.state.bullets.push(BulletState {
+                        id: bullet_id,
+                        player_id: player.id,
+                        angle: player.angle,
+                        x: player.x + (bullet_x * distance_from_player),
+                        y: player.y + (bullet_y * distance_from_player),
+                        spawn_tick: self.state.tick,
+                        remaining_life: self.config.bullet_lifetime_secs,
+                        bounces_left: self.config.bullet_bounces,
+                    });
 
-                        self.state.bullets.push(BulletState {
-                            id: bullet_id,
-                            player_id: player.id,
-                            angle: player.angle,
-                            x: player.x + (bullet_x * distance_from_player),
-                            y: player.y + (bullet_y * distance_from_player),
-                        });
+                    // No shooting from behind the spawn protection
+                    player.spawn_protection = 0.0;
+                    self.last_active.insert(player.id, Instant::now());
+                    self.stats.entry(player.id).or_default().shots_fired += 1;
+
+                    if self.config.weapon_enabled {
+                        player.ammo -= 1;
+                        player.cooldown = if player.ammo == 0 {
+                            self.config.reload_secs
+                        } else {
+                            self.config.fire_cooldown_secs
+                        };
                     }
                 },
+                // Chat is handled by the game actor, and never reaches the
+                // game
+                GameCommand::Say(_) => {},
             }
         }
+
+        Ok(())
     }
 
     pub fn init(&mut self) {}
 
+    // Place a player at the safest spawn point we can find, preferring the
+    // map's spawn points when it has any, and protect them for a little while.
+    fn spawn(&mut self, player: &mut PlayerState) {
+        let candidates = if self.spawn_points.is_empty() {
+            let rng = &mut self.rng;
+            let bounds = self.state.bounds;
+            let obstacles = &self.state.obstacles;
+
+            (0..MAX_PLACEMENT_ATTEMPTS)
+                .map(|_| random_clear_point(rng, bounds, obstacles, PLAYER_RADIUS))
+                .collect()
+        } else {
+            let mut spawn_points = self.spawn_points.clone();
+            spawn_points.shuffle(&mut self.rng);
+            spawn_points
+        };
+
+        let mut best = (f32::MIN, (0.0, 0.0));
+        for point in candidates {
+            let clearance = spawn_clearance(&self.state, point, self.config.min_spawn_distance);
+
+            if clearance > best.0 {
+                best = (clearance, point);
+            }
+            if clearance >= 0.0 {
+                break;
+            }
+        }
+
+        player.randomize(&mut self.rng, self.state.bounds);
+        player.x = (best.1).0;
+        player.y = (best.1).1;
+        player.spawn_protection = self.config.spawn_protection_secs;
+
+        self.life_started.insert(player.id, Instant::now());
+    }
+
+    /// Picks up the players and standings saved before the server restarted.
+    /// Everyone counts as disconnected until they reconnect.
+    pub fn restore(&mut self, state: GameState, stats: HashMap<u32, PlayerStats>) {
+        self.state.tick = state.tick;
+        self.state.players = state.players;
+        self.state.dead = state.dead;
+        self.state.scoreboard = state.scoreboard;
+        self.stats = stats;
+
+        let now = Instant::now();
+        let dead_players = self.state.dead.iter().map(|corpse| &corpse.player);
+        for player in self.state.players.iter().chain(dead_players) {
+            self.disconnected.insert(player.id, now);
+            self.last_active.insert(player.id, now);
+        }
+    }
+
+    /// Returns everyone's statistics so far, including the lives still going.
+    pub fn stats(&self) -> HashMap<u32, PlayerStats> {
+        let mut stats = self.stats.clone();
+
+        for (player_id, player_stats) in &mut stats {
+            if player_stats.shots_fired > 0 {
+                player_stats.accuracy =
+                    player_stats.shots_hit as f32 / player_stats.shots_fired as f32;
+            }
+
+            end_life(player_stats, self.life_started.get(player_id));
+        }
+
+        stats
+    }
+
+    fn spawn_item(&mut self) {
+        let kind = *ITEM_KINDS.choose(&mut self.rng).unwrap();
+
+        let position = if self.item_spawns.is_empty() {
+            Some(random_clear_point(
+                &mut self.rng,
+                self.state.bounds,
+                &self.state.obstacles,
+                ITEM_RADIUS,
+            ))
+        } else {
+            let items = &self.state.items;
+            let free_spawns: Vec<_> = self
+                .item_spawns
+                .iter()
+                .filter(|(x, y)| !items.iter().any(|item| item.x == *x && item.y == *y))
+                .collect();
+
+            free_spawns.choose(&mut self.rng).map(|position| **position)
+        };
+
+        if let Some((x, y)) = position {
+            let id = self.item_id_counter;
+            self.item_id_counter += 1;
+
+            self.state.items.push(ItemState { id, kind, x, y });
+        }
+    }
+
     pub fn tick(&mut self, dt: f32) {
-        // Revive the dead
+        let bounds = self.state.bounds;
+        self.state.tick += 1;
+
+        // Remove the players who didn't come back
+        let grace = Duration::from_secs(self.config.disconnect_grace_secs);
+        let gone: HashSet<u32> = self
+            .disconnected
+            .iter()
+            .filter(|(_, time)| time.elapsed() >= grace)
+            .map(|(player_id, _)| *player_id)
+            .collect();
+
+        for player_id in &gone {
+            info!("Removing disconnected player {}", player_id);
+            self.disconnected.remove(player_id);
+            self.last_active.remove(player_id);
+            self.life_started.remove(player_id);
+            self.damage_log.remove(player_id);
+        }
+        self.state.players.retain(|player| !gone.contains(&player.id));
+        self.state.dead.retain(|corpse| !gone.contains(&corpse.player.id));
+
+        // Revive the dead, somewhere safe
         let now = SystemTime::now();
-        let revived = self
+        let revived: Vec<_> = self
             .state
             .dead
-            .drain_filter(|corpse| corpse.respawn <= now)
+            .extract_if(.., |corpse| corpse.respawn <= now)
             .map(|dead| dead.player)
-            .map(|player| {
-                println!("revived player {}", player.id);
-                player
-            });
+            .collect();
+
+        for mut player in revived {
+            println!("revived player {}", player.id);
+            self.spawn(&mut player);
+            self.state.players.push(player);
+        }
+
+        // Spawn power-ups
+        if self.config.items_enabled
+            && self.state.items.len() < self.config.max_items
+            && self.next_item_spawn <= Instant::now()
+        {
+            self.spawn_item();
+            self.next_item_spawn =
+                Instant::now() + Duration::from_secs(self.config.item_spawn_interval_secs);
+        }
+
+        let previous_positions: HashMap<u32, (f32, f32)> =
+            self.state.players.iter().map(|player| (player.id, (player.x, player.y))).collect();
 
-        self.state.players.extend(revived);
+        let previous_bullets: HashMap<u32, (f32, f32)> =
+            self.state.bullets.iter().map(|bullet| (bullet.id, (bullet.x, bullet.y))).collect();
 
         // Advance bullets
         for bullet in &mut self.state.bullets {
-            let (vel_x, vel_y) = angle_to_vector(bullet.angle);
-
-            bullet.x += vel_x * BULLET_SPEED * dt;
-            bullet.y += vel_y * BULLET_SPEED * dt;
+            physics::step_bullet(bullet, bounds, dt);
         }
 
         for player in &mut self.state.players {
             // Move the player
-            let (vel_x, vel_y) = angle_to_vector(player.angle);
+            physics::step(player, self.state.physics, dt);
+
+            // Keep the players out of obstacles
+            for obstacle in &self.state.obstacles {
+                if let Some((x, y)) = obstacle.push_out(player.x, player.y, PLAYER_RADIUS) {
+                    player.x = x;
+                    player.y = y;
+                }
+            }
+
+            // Keep the players in bounds, hitting a wall kills the momentum
+            let x = player.x.max(PLAYER_RADIUS).min(bounds.0 - PLAYER_RADIUS);
+            let y = player.y.max(PLAYER_RADIUS).min(bounds.1 - PLAYER_RADIUS);
+
+            if x != player.x {
+                player.vel_x = 0.0;
+            }
+            if y != player.y {
+                player.vel_y = 0.0;
+            }
+
+            player.x = x;
+            player.y = y;
+
+            // Slowly heal
+            if self.config.health_enabled {
+                player.health = (player.health + self.config.health_regen_per_second * dt)
+                    .min(self.config.max_health);
+            }
+
+            // Cool down and reload the weapon. An empty magazine gets
+            // refilled even without a reload time.
+            if self.config.weapon_enabled {
+                player.cooldown = (player.cooldown - dt).max(0.0);
+
+                if player.cooldown == 0.0 && player.ammo == 0 {
+                    player.ammo = self.config.magazine_size;
+                }
+            }
+
+            player.spawn_protection = (player.spawn_protection - dt).max(0.0);
+
+            if let Some((x, y)) = previous_positions.get(&player.id) {
+                let distance = (player.x - x).hypot(player.y - y);
+                self.stats.entry(player.id).or_default().distance_travelled += distance;
 
-            player.x += vel_x * PLAYER_BASE_SPEED * player.throttle * dt;
-            player.y += vel_y * PLAYER_BASE_SPEED * player.throttle * dt;
+                // Anyone moving around isn't idle, pushing against a wall doesn't count
+                if distance >= IDLE_DISTANCE {
+                    self.last_active.insert(player.id, Instant::now());
+                }
+            }
 
-            // Keep the players in bounds
-            player.x = player.x.max(PLAYER_RADIUS).min(BOUNDS.0 - PLAYER_RADIUS);
-            player.y = player.y.max(PLAYER_RADIUS).min(BOUNDS.1 - PLAYER_RADIUS);
+            // Wear off power-ups
+            for effect in &mut player.effects {
+                effect.remaining -= dt;
+            }
+            player.effects.retain(|effect| effect.remaining > 0.0);
         }
 
-        // Remove out-of-bound bullets
+        // Pick up power-ups
+        let item_effect_secs = self.config.item_effect_secs;
+        for player in &mut self.state.players {
+            let picked_up: Vec<_> =
+                self.state.items.extract_if(.., |item| player.is_colliding(item)).collect();
+
+            for item in picked_up {
+                player.effects.retain(|effect| effect.kind != item.kind);
+                player.effects.push(ActiveEffect { kind: item.kind, remaining: item_effect_secs });
+            }
+        }
+
+        // Remove out-of-bound and expired bullets, and the ones absorbed by
+        // obstacles anywhere along the way they flew this tick
+        let obstacles = &self.state.obstacles;
         self.state.bullets.retain(|b| {
-            b.x > (BULLET_RADIUS)
-                && b.x < (BOUNDS.0 + BULLET_RADIUS)
+            let start = previous_bullets.get(&b.id).cloned().unwrap_or((b.x, b.y));
+
+            b.remaining_life.is_none_or(|life| life > 0.0)
+                && b.x > (BULLET_RADIUS)
+                && b.x < (bounds.0 + BULLET_RADIUS)
                 && b.y > (BULLET_RADIUS)
-                && b.y < (BOUNDS.1 + BULLET_RADIUS)
+                && b.y < (bounds.1 + BULLET_RADIUS)
+                && !obstacles
+                    .iter()
+                    .any(|obstacle| obstacle.blocks_segment(start, (b.x, b.y), BULLET_RADIUS))
         });
 
-        // count collisions
+        // count collisions, sweeping every pair of players along their moves this tick
+        let start_of = |player: &PlayerState| {
+            previous_positions.get(&player.id).cloned().unwrap_or((player.x, player.y))
+        };
+
+        let player_grid = &mut self.player_grid;
+        let candidates = &mut self.candidates;
+        index_players(player_grid, &self.state.players, start_of);
+        let players = &self.state.players;
+
         let mut dead_players = HashSet::new();
-        for player in &self.state.players {
-            for other in &self.state.players {
-                if player.id != other.id && player.is_colliding(other) {
+        for (i, player) in self.state.players.iter().enumerate() {
+            let path = (start_of(player), (player.x, player.y));
+            nearby_players(player_grid, players.len(), path, player.radius(), candidates);
+
+            for other in candidates.iter().filter(|j| **j > i).map(|j| &players[*j]) {
+                let contact = sweep(
+                    (start_of(player), (player.x, player.y)),
+                    (start_of(other), (other.x, other.y)),
+                    player.radius() + other.radius(),
+                );
+
+                if contact.is_some() && !player.is_protected() && !other.is_protected() {
                     dead_players.insert(player.id);
                     dead_players.insert(other.id);
                 }
             }
         }
 
-        for mut player in self.state.players.drain_filter(|player| dead_players.contains(&player.id)) {
-            player.randomize(&mut self.rng, BOUNDS);
+        for mut player in
+            self.state.players.extract_if(.., |player| dead_players.contains(&player.id))
+        {
+            self.damage_log.remove(&player.id);
+            reset_player(&mut player, &self.config);
+
+            let stats = self.stats.entry(player.id).or_default();
+            stats.collision_deaths += 1;
+            end_life(stats, self.life_started.remove(&player.id).as_ref());
+
             self.state
                 .dead
                 .push(DeadPlayer { respawn: SystemTime::now() + DEAD_PUNISH, player });
         }
 
-        // count the dead
-        let mut hits = vec![];
-        let mut used_bullets = vec![];
-        for bullet in &mut self.state.bullets {
-            let deceased = self.state.players.drain_filter(|player| {
-                if player.is_colliding(bullet) && bullet.player_id != player.id {
-                    println!(
-                        "Player {} killed player {} at ({}, {})",
-                        bullet.player_id, player.id, bullet.x, bullet.y
-                    );
-                    hits.push(bullet.player_id);
-                    used_bullets.push(bullet.id);
-
-                    true
-                } else {
-                    false
+        // Find every bullet contact during this tick, and resolve them in the
+        // order they happened. Ties go to the lower bullet ID, then to the lower
+        // player ID, so a bullet only ever hits the first player in its way.
+        let player_grid = &mut self.player_grid;
+        let candidates = &mut self.candidates;
+        index_players(player_grid, &self.state.players, start_of);
+        let players = &self.state.players;
+
+        let mut contacts = vec![];
+        for bullet in &self.state.bullets {
+            // Bullets fired this tick start where they are
+            let bullet_start =
+                previous_bullets.get(&bullet.id).cloned().unwrap_or((bullet.x, bullet.y));
+
+            let path = (bullet_start, (bullet.x, bullet.y));
+            nearby_players(player_grid, players.len(), path, bullet.radius(), candidates);
+
+            let targets = candidates.iter().map(|i| &players[*i]);
+            for player in targets.filter(|p| p.id != bullet.player_id && !p.is_protected()) {
+                let contact = sweep(
+                    (bullet_start, (bullet.x, bullet.y)),
+                    (start_of(player), (player.x, player.y)),
+                    bullet.radius() + player.radius(),
+                );
+
+                if let Some(time) = contact {
+                    contacts.push((time, bullet.id, player.id, bullet.player_id));
                 }
-            });
-            for mut player in deceased {
-                // Reset their survival time bonus
-                self.survival_times.insert(player.id, Instant::now() + Duration::from_secs(SURVIVAL_TIMEOUT));
-
-                player.randomize(&mut self.rng, BOUNDS);
-                self.state
-                    .dead
-                    .push(DeadPlayer { respawn: SystemTime::now() + DEAD_PUNISH, player });
             }
         }
+        contacts.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+        // count the dead
+        let mut kills = vec![];
+        let mut used_bullets = HashSet::new();
+        let mut deceased = HashSet::new();
+        for (_, bullet_id, player_id, shooter_id) in contacts {
+            if used_bullets.contains(&bullet_id) || deceased.contains(&player_id) {
+                continue;
+            }
+
+            let player = match self.state.players.iter_mut().find(|p| p.id == player_id) {
+                Some(player) => player,
+                None => continue,
+            };
+
+            used_bullets.insert(bullet_id);
+            self.stats.entry(shooter_id).or_default().shots_hit += 1;
+
+            if player.has_effect(ItemKind::Shield) {
+                continue;
+            }
+
+            player.health -= self.config.bullet_damage;
+            player.last_damaged_by = Some(shooter_id);
+            self.damage_log.entry(player_id).or_default().push((shooter_id, Instant::now()));
+
+            if !self.config.health_enabled || player.health <= 0.0 {
+                println!(
+                    "Player {} killed player {} at ({}, {})",
+                    shooter_id, player_id, player.x, player.y
+                );
+                kills.push((shooter_id, player_id));
+                deceased.insert(player_id);
+            }
+        }
+
+        for mut player in self.state.players.extract_if(.., |player| deceased.contains(&player.id)) {
+            // Reset their survival time bonus
+            self.survival_times
+                .insert(player.id, Instant::now() + Duration::from_secs(SURVIVAL_TIMEOUT));
+
+            reset_player(&mut player, &self.config);
+
+            let stats = self.stats.entry(player.id).or_default();
+            stats.bullet_deaths += 1;
+            end_life(stats, self.life_started.remove(&player.id).as_ref());
+
+            self.state.dead.push(DeadPlayer { respawn: SystemTime::now() + DEAD_PUNISH, player });
+        }
 
         // Clear out used bullets
         self.state.bullets.retain(|b| !used_bullets.contains(&b.id));
 
-        // Update the scoreboard
-        for player_id in hits {
-            *self.state.scoreboard.entry(player_id).or_default() += 1;
+        // Update the scoreboard, the last hitter gets the kill
+        let assist_window = Duration::from_secs(self.config.assist_window_secs);
+        for (killer_id, victim_id) in kills {
+            let stats = self.stats.entry(killer_id).or_default();
+            stats.kills += 1;
+            stats.kill_points += award_points(&mut self.state, killer_id, self.config.kill_points);
+
+            let assists: HashSet<u32> = self
+                .damage_log
+                .remove(&victim_id)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(attacker_id, time)| {
+                    *attacker_id != killer_id && time.elapsed() <= assist_window
+                })
+                .map(|(attacker_id, _)| attacker_id)
+                .collect();
+
+            for assist_id in assists {
+                self.stats.entry(assist_id).or_default().assist_points +=
+                    award_points(&mut self.state, assist_id, self.config.assist_points);
+            }
+        }
+
+        // Forget about old damage
+        for hits in self.damage_log.values_mut() {
+            hits.retain(|(_, time)| time.elapsed() <= assist_window);
         }
 
         // Reward players for staying alive
+        let idle_timeout = self.config.idle_timeout_secs;
         for (player_id, next_reward_time) in &mut self.survival_times {
             if *next_reward_time <= Instant::now() {
-                *self.state.scoreboard.entry(*player_id).or_default() += 1;
+                let idle_periods = idle_periods(self.last_active.get(player_id), idle_timeout);
+
+                let (points, interval) = match self.config.idle_survival {
+                    _ if idle_periods == 0 => (1, SURVIVAL_POINT_INTERVAL),
+                    IdleSurvival::Keep => (1, SURVIVAL_POINT_INTERVAL),
+                    IdleSurvival::Decay => {
+                        (1, SURVIVAL_POINT_INTERVAL << idle_periods.min(MAX_IDLE_DECAY))
+                    },
+                    IdleSurvival::None => (0, SURVIVAL_POINT_INTERVAL),
+                };
+
+                self.stats.entry(*player_id).or_default().survival_points +=
+                    award_points(&mut self.state, *player_id, points);
 
-                *next_reward_time = Instant::now() + Duration::from_secs(SURVIVAL_POINT_INTERVAL);
+                *next_reward_time = Instant::now() + Duration::from_secs(interval);
             }
         }
+
+        // Let everyone know who's actually playing
+        for player in &mut self.state.players {
+            player.status = if self.disconnected.contains_key(&player.id) {
+                PlayerStatus::Disconnected
+            } else if idle_periods(self.last_active.get(&player.id), idle_timeout) > 0 {
+                PlayerStatus::Idle
+            } else {
+                PlayerStatus::Active
+            };
+        }
+    }
+}
+
+// Indexes the players along their moves this tick. Up to LINEAR_SCAN_LIMIT
+// players it's faster to check all of them, so the grid is left empty.
+fn index_players<F>(grid: &mut SpatialGrid<usize>, players: &[PlayerState], start_of: F)
+where
+    F: Fn(&PlayerState) -> (f32, f32),
+{
+    grid.clear();
+    if players.len() > LINEAR_SCAN_LIMIT {
+        for (i, player) in players.iter().enumerate() {
+            grid.insert(i, start_of(player), (player.x, player.y), player.radius());
+        }
+    }
+}
+
+// Puts the indexes of the players a circle of `radius` moving along `path` might
+// touch into `candidates`
+fn nearby_players(
+    grid: &SpatialGrid<usize>,
+    player_count: usize,
+    (from, to): ((f32, f32), (f32, f32)),
+    radius: f32,
+    candidates: &mut Vec<usize>,
+) {
+    if player_count > LINEAR_SCAN_LIMIT {
+        grid.query_into(from, to, radius, candidates);
+    } else {
+        candidates.clear();
+        candidates.extend(0..player_count);
     }
 }
 
@@ -261,3 +887,367 @@ impl Game {
 fn angle_to_vector(angle: f32) -> (f32, f32) {
     (angle.cos(), angle.sin())
 }
+
+/// Returns the earliest moment, as a fraction of the tick, at which two circles
+/// moving in straight lines from and to the given points come closer than
+/// `radii`. None if they don't touch during the tick.
+fn sweep(
+    ((a_x0, a_y0), (a_x1, a_y1)): ((f32, f32), (f32, f32)),
+    ((b_x0, b_y0), (b_x1, b_y1)): ((f32, f32), (f32, f32)),
+    radii: f32,
+) -> Option<f32> {
+    // Look at the motion of b relative to a
+    let (p_x, p_y) = (b_x0 - a_x0, b_y0 - a_y0);
+    let (v_x, v_y) = ((b_x1 - b_x0) - (a_x1 - a_x0), (b_y1 - b_y0) - (a_y1 - a_y0));
+
+    let c = p_x * p_x + p_y * p_y - radii * radii;
+    if c < 0.0 {
+        // Already touching at the start of the tick
+        return Some(0.0);
+    }
+
+    let a = v_x * v_x + v_y * v_y;
+    let b = 2.0 * (p_x * v_x + p_y * v_y);
+    let discriminant = b * b - 4.0 * a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+
+    let time = (-b - discriminant.sqrt()) / (2.0 * a);
+    if (0.0..=1.0).contains(&time) {
+        Some(time)
+    } else {
+        None
+    }
+}
+
+// Adds points to the scoreboard, taking power-ups into account
+// Returns the points actually awarded, after power-ups
+fn award_points(state: &mut GameState, player_id: u32, points: u32) -> u32 {
+    let multiplier = match state.players.iter().find(|p| p.id == player_id) {
+        Some(player) if player.has_effect(ItemKind::ScoreMultiplier) => SCORE_MULTIPLIER,
+        _ => 1,
+    };
+
+    *state.scoreboard.entry(player_id).or_default() += points * multiplier;
+    points * multiplier
+}
+
+// Keeps track of the longest life when a life ends, or is still going
+fn end_life(stats: &mut PlayerStats, life_started: Option<&Instant>) {
+    if let Some(time) = life_started {
+        let life = time.elapsed();
+        let life = life.as_secs() as f32 + life.subsec_micros() as f32 / 1e6;
+
+        stats.longest_life = stats.longest_life.max(life);
+    }
+}
+
+// How many whole idle timeouts passed since the player was last active
+fn idle_periods(last_active: Option<&Instant>, idle_timeout_secs: u64) -> u64 {
+    match last_active {
+        Some(time) if idle_timeout_secs > 0 => time.elapsed().as_secs() / idle_timeout_secs,
+        _ => 0,
+    }
+}
+
+// Clears everything a player loses when they die
+fn reset_player(player: &mut PlayerState, config: &GameConfig) {
+    player.health = config.max_health;
+    player.last_damaged_by = None;
+    player.effects.clear();
+    player.ammo = config.magazine_size;
+    player.cooldown = 0.0;
+}
+
+fn random_clear_point(
+    rng: &mut impl Rng,
+    (bound_right, bound_bottom): (f32, f32),
+    obstacles: &[Obstacle],
+    radius: f32,
+) -> (f32, f32) {
+    let mut point = (0.0, 0.0);
+
+    for _ in 0..MAX_PLACEMENT_ATTEMPTS {
+        point = (
+            rng.gen_range(radius, bound_right - radius),
+            rng.gen_range(radius, bound_bottom - radius),
+        );
+
+        if !obstacles.iter().any(|obstacle| obstacle.is_colliding(point.0, point.1, radius)) {
+            break;
+        }
+    }
+
+    point
+}
+
+// How much room a player spawning at `point` would have. Negative when it's
+// closer than `min_distance` to another player, or in the way of a bullet.
+fn spawn_clearance(state: &GameState, point: (f32, f32), min_distance: f32) -> f32 {
+    let players = state.players.iter().map(|player| {
+        ((player.x - point.0).powi(2) + (player.y - point.1).powi(2)).sqrt() - min_distance
+    });
+
+    let bullets = state.bullets.iter().map(|bullet| {
+        let (vel_x, vel_y) = angle_to_vector(bullet.angle);
+        let reach = BULLET_SPEED * SPAWN_BULLET_LOOKAHEAD_SECS;
+        let end = (bullet.x + vel_x * reach, bullet.y + vel_y * reach);
+
+        distance_to_segment(point, (bullet.x, bullet.y), end)
+            - (PLAYER_RADIUS + BULLET_RADIUS) * 2.0
+    });
+
+    players.chain(bullets).fold(f32::MAX, f32::min)
+}
+
+fn distance_to_segment(point: (f32, f32), from: (f32, f32), to: (f32, f32)) -> f32 {
+    let (d_x, d_y) = (to.0 - from.0, to.1 - from.1);
+    let length = d_x * d_x + d_y * d_y;
+    let t = if length > 0.0 {
+        (((point.0 - from.0) * d_x + (point.1 - from.1) * d_y) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    ((from.0 + d_x * t - point.0).powi(2) + (from.1 + d_y * t - point.1).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / TICKS_PER_SECOND;
+
+    fn weapon_game(config: GameConfig) -> Game {
+        let mut game = Game::new(Map::default(), GameConfig { weapon_enabled: true, ..config });
+        game.add_player(1);
+        game
+    }
+
+    fn player(game: &Game, player_id: u32) -> &PlayerState {
+        game.state.players.iter().find(|p| p.id == player_id).unwrap()
+    }
+
+    // Enough ticks for the given number of seconds to pass
+    fn run(game: &mut Game, secs: f32) {
+        for _ in 0..(secs / DT).ceil() as usize + 1 {
+            game.tick(DT);
+        }
+    }
+
+    #[test]
+    fn fire_cools_down_between_shots() {
+        let mut game = weapon_game(GameConfig::default());
+
+        assert_eq!(game.handle_cmd(1, GameCommand::Fire), Ok(()));
+        assert_eq!(game.handle_cmd(1, GameCommand::Fire), Err(Rejection::CoolingDown));
+
+        let cooldown = game.config.fire_cooldown_secs;
+        run(&mut game, cooldown);
+        assert_eq!(game.handle_cmd(1, GameCommand::Fire), Ok(()));
+    }
+
+    #[test]
+    fn empty_magazine_reloads() {
+        let config = GameConfig { magazine_size: 2, fire_cooldown_secs: 0.0, ..Default::default() };
+        let mut game = weapon_game(config);
+
+        assert_eq!(game.handle_cmd(1, GameCommand::Fire), Ok(()));
+        assert_eq!(game.handle_cmd(1, GameCommand::Fire), Ok(()));
+        assert_eq!(player(&game, 1).ammo, 0);
+        assert_eq!(game.handle_cmd(1, GameCommand::Fire), Err(Rejection::Reloading));
+
+        let reload = game.config.reload_secs;
+        run(&mut game, reload);
+        assert_eq!(player(&game, 1).ammo, 2);
+        assert_eq!(game.handle_cmd(1, GameCommand::Fire), Ok(()));
+    }
+
+    #[test]
+    fn empty_magazine_without_reload_time_is_rejected_until_refilled() {
+        let config = GameConfig {
+            magazine_size: 1,
+            fire_cooldown_secs: 0.0,
+            reload_secs: 0.0,
+            ..Default::default()
+        };
+        let mut game = weapon_game(config);
+
+        assert_eq!(game.handle_cmd(1, GameCommand::Fire), Ok(()));
+        assert_eq!(game.handle_cmd(1, GameCommand::Fire), Err(Rejection::Reloading));
+        assert_eq!(player(&game, 1).ammo, 0);
+
+        game.tick(DT);
+        assert_eq!(player(&game, 1).ammo, 1);
+    }
+
+    #[test]
+    fn restored_player_with_empty_magazine_is_rejected() {
+        let mut game = weapon_game(GameConfig::default());
+        let mut state = game.state.clone();
+        state.players[0].ammo = 0;
+        state.players[0].cooldown = 0.0;
+        game.restore(state, HashMap::new());
+
+        assert_eq!(game.handle_cmd(1, GameCommand::Fire), Err(Rejection::Reloading));
+        game.tick(DT);
+        assert_eq!(player(&game, 1).ammo, game.config.magazine_size);
+    }
+
+    // Puts a player at rest at the given position, without spawn protection
+    fn place(game: &mut Game, player_id: u32, (x, y): (f32, f32)) {
+        game.add_player(player_id);
+        let player = game.state.players.iter_mut().find(|p| p.id == player_id).unwrap();
+        player.x = x;
+        player.y = y;
+        player.throttle = 0.0;
+        player.spawn_protection = 0.0;
+    }
+
+    fn shoot(game: &mut Game, id: u32, player_id: u32, (x, y): (f32, f32)) {
+        game.state.bullets.push(BulletState {
+            id,
+            player_id,
+            angle: 0.0,
+            x,
+            y,
+            spawn_tick: game.state.tick,
+            remaining_life: None,
+            bounces_left: 0,
+        });
+    }
+
+    fn is_dead(game: &Game, player_id: u32) -> bool {
+        game.state.dead.iter().any(|corpse| corpse.player.id == player_id)
+    }
+
+    #[test]
+    fn fast_bullet_does_not_tunnel_through_player() {
+        let mut game = Game::new(Map::default(), GameConfig::default());
+        place(&mut game, 1, (100.0, 100.0));
+        place(&mut game, 2, (500.0, 500.0));
+
+        // Far enough in one tick to end up past the player
+        shoot(&mut game, 1, 1, (470.0, 500.0));
+        game.tick(0.1);
+
+        assert!(is_dead(&game, 2));
+        assert!(game.state.bullets.is_empty());
+        assert_eq!(game.stats[&1].shots_hit, 1);
+    }
+
+    #[test]
+    fn bullet_hits_the_first_player_in_its_way() {
+        let mut game = Game::new(Map::default(), GameConfig::default());
+        place(&mut game, 1, (100.0, 100.0));
+        place(&mut game, 2, (560.0, 500.0));
+        place(&mut game, 3, (520.0, 500.0));
+
+        shoot(&mut game, 1, 1, (470.0, 500.0));
+        game.tick(0.2);
+
+        assert!(is_dead(&game, 3));
+        assert!(!is_dead(&game, 2));
+    }
+
+    #[test]
+    fn simultaneous_hits_go_to_the_lower_bullet_id() {
+        let mut game = Game::new(Map::default(), GameConfig::default());
+        place(&mut game, 1, (100.0, 100.0));
+        place(&mut game, 2, (100.0, 300.0));
+        place(&mut game, 3, (500.0, 500.0));
+
+        // Both bullets reach the player at the same moment
+        shoot(&mut game, 8, 2, (470.0, 500.0));
+        shoot(&mut game, 7, 1, (470.0, 500.0));
+        game.tick(DT);
+
+        assert!(is_dead(&game, 3));
+        assert_eq!(game.stats[&1].shots_hit, 1);
+        assert_eq!(game.stats.get(&2).map_or(0, |stats| stats.shots_hit), 0);
+        assert_eq!(game.state.bullets.iter().map(|b| b.id).collect::<Vec<_>>(), vec![8]);
+    }
+
+    #[test]
+    fn fast_bullet_does_not_tunnel_through_player_in_a_crowd() {
+        let mut game = Game::new(Map::default(), GameConfig::default());
+
+        // Enough players for the grid to be used, spread out so none collide
+        for i in 0..=LINEAR_SCAN_LIMIT as u32 {
+            let (column, row) = ((i % 20) as f32, (i / 20) as f32);
+            place(&mut game, i + 10, (100.0 + column * 100.0, 1000.0 + row * 100.0));
+        }
+        place(&mut game, 1, (100.0, 100.0));
+        place(&mut game, 2, (500.0, 500.0));
+
+        shoot(&mut game, 1, 1, (470.0, 500.0));
+        game.tick(0.1);
+
+        assert!(is_dead(&game, 2));
+        assert_eq!(game.state.dead.len(), 1);
+    }
+
+    #[test]
+    fn fast_bullet_does_not_tunnel_through_obstacle() {
+        let wall = Obstacle::Polygon {
+            points: vec![(500.0, 400.0), (502.0, 400.0), (502.0, 600.0), (500.0, 600.0)],
+        };
+        let map = Map { obstacles: vec![wall], ..Map::default() };
+        let mut game = Game::new(map, GameConfig::default());
+        place(&mut game, 1, (100.0, 100.0));
+        place(&mut game, 2, (560.0, 500.0));
+
+        shoot(&mut game, 1, 1, (470.0, 500.0));
+        game.tick(0.1);
+
+        assert!(game.state.bullets.is_empty());
+        assert!(!is_dead(&game, 2));
+    }
+
+    #[test]
+    fn non_finite_commands_are_rejected() {
+        let square = Obstacle::Polygon {
+            points: vec![(400.0, 400.0), (600.0, 400.0), (600.0, 600.0), (400.0, 600.0)],
+        };
+        let map = Map { obstacles: vec![square], ..Map::default() };
+        let mut game = Game::new(map, GameConfig { momentum_enabled: true, ..Default::default() });
+        place(&mut game, 1, (300.0, 500.0));
+
+        assert_eq!(game.handle_cmd(1, GameCommand::Rotate(f32::NAN)), Err(Rejection::NotANumber));
+        let infinite_throttle = GameCommand::Throttle(f32::INFINITY);
+        assert_eq!(game.handle_cmd(1, infinite_throttle), Err(Rejection::NotANumber));
+
+        // Heading straight for the obstacle, which has to push the player out
+        game.state.players[0].angle = 0.0;
+        game.handle_cmd(1, GameCommand::Rotate(0.0)).unwrap();
+        game.handle_cmd(1, GameCommand::Throttle(1.0)).unwrap();
+        run(&mut game, 1.0);
+
+        let player = player(&game, 1);
+        assert!(player.x.is_finite() && player.y.is_finite());
+        assert!(player.x < 400.0);
+    }
+
+    #[test]
+    fn sweep_catches_circles_passing_through_each_other() {
+        // Both end up on the other's side, never overlapping at a tick boundary
+        let contact = sweep(((0.0, 0.0), (100.0, 0.0)), ((100.0, 0.0), (0.0, 0.0)), 20.0);
+        assert_eq!(contact, Some(0.4));
+
+        assert_eq!(sweep(((0.0, 0.0), (100.0, 0.0)), ((0.0, 50.0), (100.0, 50.0)), 20.0), None);
+        assert_eq!(sweep(((0.0, 0.0), (0.0, 0.0)), ((10.0, 0.0), (10.0, 0.0)), 20.0), Some(0.0));
+    }
+
+    #[test]
+    fn sweep_ignores_nan() {
+        let contact = sweep(((f32::NAN, 0.0), (100.0, 0.0)), ((50.0, 0.0), (50.0, 0.0)), 20.0);
+        assert_eq!(contact, None);
+    }
+
+    #[test]
+    fn empty_magazine_size_is_invalid() {
+        assert!(GameConfig::default().validate().is_ok());
+        assert!(GameConfig { magazine_size: 0, ..Default::default() }.validate().is_err());
+    }
+}
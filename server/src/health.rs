@@ -0,0 +1,228 @@
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+lazy_static! {
+    pub static ref HEALTH: Health = Health::default();
+}
+
+// A game loop is considered stuck if it hasn't finished a tick for this long
+const STALE_TICK: Duration = Duration::from_secs(2);
+
+/// Tracks whether the game loops are still running, for `/healthz` and
+/// `/readyz`. Each room's loop reports every completed tick, and the room's
+/// watchdog restarts it if it crashes. Loops which are done, like a replay
+/// that played to the end, don't count anymore.
+#[derive(Debug, Default)]
+pub struct Health {
+    rooms: Mutex<HashMap<String, Arc<LoopHealth>>>,
+    shutting_down: AtomicBool,
+}
+
+#[derive(Debug)]
+pub struct LoopHealth {
+    started: Instant,
+    // Milliseconds since `started`, or 0 before the first tick
+    last_tick_millis: AtomicU64,
+    crashed: AtomicBool,
+    gave_up: AtomicBool,
+    finished: AtomicBool,
+    restarts: AtomicU64,
+    // Commands sent to the game loop which it hasn't picked up yet
+    queued: AtomicI64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthStatus {
+    pub healthy: bool,
+    pub ready: bool,
+    pub reason: Option<String>,
+    pub rooms: HashMap<String, RoomHealth>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomHealth {
+    pub healthy: bool,
+    pub last_tick_secs_ago: Option<f64>,
+    pub restarts: u64,
+}
+
+impl Health {
+    pub fn register(&self, room: &str) -> Arc<LoopHealth> {
+        let health = Arc::new(LoopHealth::new());
+        self.rooms.lock().unwrap().insert(room.to_string(), health.clone());
+        health
+    }
+
+    pub fn unregister(&self, room: &str) {
+        self.rooms.lock().unwrap().remove(room);
+    }
+
+    pub fn set_shutting_down(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    /// Healthy while every room's game loop is ticking. Ready once the main
+    /// room has ticked, until the server starts shutting down, so no new
+    /// players get sent here.
+    pub fn status(&self, main_room: &str) -> HealthStatus {
+        let mut reason = None;
+        let mut main_room_ticked = false;
+
+        let rooms = self
+            .rooms
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, health)| !health.finished.load(Ordering::SeqCst))
+            .map(|(room, health)| {
+                let last_tick = health.since_last_tick();
+                let problem = health.problem(last_tick);
+
+                if let Some(problem) = problem {
+                    reason = Some(format!("{} in room {}", problem, room));
+                }
+                if room == main_room {
+                    main_room_ticked = last_tick.is_some();
+                }
+
+                let room_health = RoomHealth {
+                    healthy: problem.is_none(),
+                    last_tick_secs_ago: last_tick.map(|since| as_millis(since) as f64 / 1000.0),
+                    restarts: health.restarts.load(Ordering::Relaxed),
+                };
+
+                (room.clone(), room_health)
+            })
+            .collect();
+
+        let healthy = reason.is_none();
+        let ready = healthy && main_room_ticked && !self.shutting_down.load(Ordering::SeqCst);
+
+        HealthStatus { healthy, ready, reason, rooms }
+    }
+}
+
+impl LoopHealth {
+    fn new() -> LoopHealth {
+        LoopHealth {
+            started: Instant::now(),
+            last_tick_millis: AtomicU64::new(0),
+            crashed: AtomicBool::new(false),
+            gave_up: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            restarts: AtomicU64::new(0),
+            queued: AtomicI64::new(0),
+        }
+    }
+
+    pub fn record_tick(&self) {
+        let millis = as_millis(self.started.elapsed()).max(1);
+        self.last_tick_millis.store(millis, Ordering::Relaxed);
+    }
+
+    pub fn record_crash(&self) {
+        self.crashed.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the game loop crashed since the last call.
+    pub fn take_crash(&self) -> bool {
+        self.crashed.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn record_restart(&self) {
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_queued(&self) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dequeued(&self) {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// How many commands a stopped game loop never got to, which are lost.
+    pub fn take_queued(&self) -> i64 {
+        self.queued.swap(0, Ordering::Relaxed)
+    }
+
+    /// The game loop stopped ticking because it's done, not because it's stuck.
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
+    /// The game loop kept crashing, so the watchdog stopped restarting it.
+    pub fn give_up(&self) {
+        self.gave_up.store(true, Ordering::SeqCst);
+    }
+
+    fn since_last_tick(&self) -> Option<Duration> {
+        match self.last_tick_millis.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Some(
+                self.started
+                    .elapsed()
+                    .checked_sub(Duration::from_millis(millis))
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+
+    fn problem(&self, since_last_tick: Option<Duration>) -> Option<&'static str> {
+        if self.gave_up.load(Ordering::SeqCst) {
+            Some("the game loop keeps crashing")
+        } else if since_last_tick.is_some_and(|since| since > STALE_TICK) {
+            Some("the game loop stopped ticking")
+        } else {
+            None
+        }
+    }
+}
+
+fn as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A room whose loop last ticked a while ago
+    fn register_stale(health: &Health, room: &str) -> Arc<LoopHealth> {
+        let stale = LoopHealth { started: Instant::now() - STALE_TICK * 2, ..LoopHealth::new() };
+        stale.last_tick_millis.store(1, Ordering::Relaxed);
+
+        let stale = Arc::new(stale);
+        health.rooms.lock().unwrap().insert(room.to_string(), stale.clone());
+        stale
+    }
+
+    #[test]
+    fn stuck_loops_are_unhealthy() {
+        let health = Health::default();
+        health.register("main").record_tick();
+        register_stale(&health, "other");
+
+        let status = health.status("main");
+        assert!(!status.healthy);
+        assert_eq!(status.reason.unwrap(), "the game loop stopped ticking in room other");
+    }
+
+    #[test]
+    fn finished_loops_are_left_out() {
+        let health = Health::default();
+        health.register("main").record_tick();
+        register_stale(&health, "replay").finish();
+
+        let status = health.status("main");
+        assert!(status.healthy && status.ready);
+        assert!(!status.rooms.contains_key("replay"));
+    }
+}
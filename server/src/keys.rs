@@ -0,0 +1,433 @@
+use crate::{actors::game_actor::HUMAN_TAG, bots::BOT_TAG};
+use failure::{format_err, Error};
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    fs::File,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::RwLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const KEY_LENGTH: usize = 32;
+const KEY_ID_LENGTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    Player,
+    /// A player who plays by hand, from the spectator page. Their team names
+    /// are tagged so everybody can tell them from bots.
+    Human,
+    Admin,
+    Spectator,
+}
+
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Role, Error> {
+        match s {
+            "player" => Ok(Role::Player),
+            "human" => Ok(Role::Human),
+            "admin" => Ok(Role::Admin),
+            "spectator" => Ok(Role::Spectator),
+            _ => Err(format_err!("Unknown role {}, expected player, human, admin or spectator", s)),
+        }
+    }
+}
+
+/// An API key as stored in the key file. Only a hash of the key itself is
+/// kept, the `id` is what the rest of the server uses to refer to it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub hash: String,
+    /// The only team name this key can play as, if set.
+    #[serde(default)]
+    pub team_name: Option<String>,
+    /// Rooms the key can join, empty for all of them.
+    #[serde(default)]
+    pub rooms: Vec<String>,
+    #[serde(default)]
+    pub role: Role,
+    /// Commands per second, instead of the server's default.
+    #[serde(default)]
+    pub rate_limit: Option<u32>,
+    /// Unix timestamp after which the key stops working.
+    #[serde(default)]
+    pub expires: Option<u64>,
+}
+
+impl ApiKey {
+    pub fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| unix_time() >= expires)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KeyFile {
+    pub keys: Vec<ApiKey>,
+}
+
+impl KeyFile {
+    /// A missing file is treated as an empty one, so the first
+    /// `tokyo-server keys generate` can create it.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<KeyFile, Error> {
+        if !path.as_ref().exists() {
+            return Ok(KeyFile::default());
+        }
+
+        let key_file = serde_json::from_reader(File::open(path)?)?;
+        Ok(key_file)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let tmp_path = path.as_ref().with_extension("tmp");
+        serde_json::to_writer_pretty(File::create(&tmp_path)?, self)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Adds a new key and returns it, this is the only time the key itself is
+    /// ever available.
+    pub fn generate(
+        &mut self,
+        team_name: Option<String>,
+        role: Role,
+        rooms: Vec<String>,
+        rate_limit: Option<u32>,
+        expires_in: Option<Duration>,
+    ) -> Result<(String, &ApiKey), Error> {
+        if let Some(team_name) = &team_name {
+            if is_tagged(team_name) {
+                return Err(format_err!(
+                    "Team names can't start with {} or {}",
+                    BOT_TAG,
+                    HUMAN_TAG
+                ));
+            }
+
+            if let Some(owner) = self.team_owner(team_name) {
+                return Err(format_err!(
+                    "The team name {} is already locked to {}",
+                    team_name,
+                    owner
+                ));
+            }
+        }
+
+        let key = random_string(KEY_LENGTH);
+        let id = loop {
+            let id = random_string(KEY_ID_LENGTH);
+            if !self.keys.iter().any(|key| key.id == id) {
+                break id;
+            }
+        };
+
+        self.keys.push(ApiKey {
+            id,
+            hash: hash_key(&key),
+            team_name,
+            rooms,
+            role,
+            rate_limit,
+            expires: expires_in.map(|expires_in| unix_time() + expires_in.as_secs()),
+        });
+
+        Ok((key, self.keys.last().unwrap()))
+    }
+
+    pub fn revoke(&mut self, id: &str) -> Option<ApiKey> {
+        let index = self.keys.iter().position(|key| key.id == id)?;
+        Some(self.keys.remove(index))
+    }
+
+    pub fn find(&self, key: &str) -> Option<&ApiKey> {
+        let hash = hash_key(key);
+        self.keys.iter().find(|key| key.hash == hash)
+    }
+
+    /// The ID of the key the team name is locked to, ignoring case so nobody
+    /// can pass for a team by changing a letter or two.
+    pub fn team_owner(&self, team_name: &str) -> Option<&str> {
+        let team_name = team_name.to_lowercase();
+        self.keys
+            .iter()
+            .find(|key| key.team_name.as_ref().is_some_and(|name| name.to_lowercase() == team_name))
+            .map(|key| key.id.as_str())
+    }
+}
+
+/// The key file as seen by the running server. It's reloaded when it changed
+/// on disk, on a timer, SIGHUP or an admin request, so generated and revoked
+/// keys take effect without a restart.
+#[derive(Debug)]
+pub struct KeyStore {
+    path: PathBuf,
+    dev_mode: bool,
+    keys: RwLock<(Option<SystemTime>, KeyFile)>,
+}
+
+impl KeyStore {
+    pub fn open<P: Into<PathBuf>>(path: P, dev_mode: bool) -> Result<KeyStore, Error> {
+        let path = path.into();
+        let modified = modified_time(&path);
+        let key_file = KeyFile::load(&path)?;
+
+        Ok(KeyStore { path, dev_mode, keys: RwLock::new((modified, key_file)) })
+    }
+
+    /// The metadata for a key, if it exists and hasn't expired. In dev mode,
+    /// unknown keys are let in as players without a team name lock.
+    pub fn authenticate(&self, key: &str) -> Option<ApiKey> {
+        let keys = self.keys.read().unwrap();
+        match keys.1.find(key) {
+            Some(api_key) if !api_key.is_expired() => Some(api_key.clone()),
+            Some(_) => None,
+            None if self.dev_mode => {
+                let hash = hash_key(key);
+                Some(ApiKey {
+                    id: format!("dev-{}", &hash[..KEY_ID_LENGTH]),
+                    hash,
+                    team_name: None,
+                    rooms: vec![],
+                    role: Role::Player,
+                    rate_limit: None,
+                    expires: None,
+                })
+            },
+            None => None,
+        }
+    }
+
+    /// Every key in the key file which hasn't expired.
+    pub fn list(&self) -> Vec<ApiKey> {
+        let keys = self.keys.read().unwrap();
+        keys.1.keys.iter().filter(|key| !key.is_expired()).cloned().collect()
+    }
+
+    /// Whether a key which was accepted earlier still works.
+    pub fn is_valid(&self, api_key: &ApiKey) -> bool {
+        let keys = self.keys.read().unwrap();
+        let revoked = !keys.1.keys.iter().any(|key| key.hash == api_key.hash);
+        !api_key.is_expired() && (!revoked || self.dev_mode)
+    }
+
+    /// Resolves the team name for a key, which can only use its own locked
+    /// name, or any name not locked to another key.
+    pub fn team_name(&self, api_key: &ApiKey, requested: &str) -> Result<String, String> {
+        if let Some(team_name) = &api_key.team_name {
+            return Ok(team_name.clone());
+        }
+
+        if is_tagged(requested) {
+            return Err(format!("Team names can't start with {} or {}", BOT_TAG, HUMAN_TAG));
+        }
+
+        match self.keys.read().unwrap().1.team_owner(requested) {
+            Some(_) => Err(format!("The team name {} belongs to another API key", requested)),
+            None => Ok(requested.to_string()),
+        }
+    }
+
+    /// Reloads the key file if it was modified since it was last loaded.
+    pub fn reload_if_changed(&self) {
+        let modified = modified_time(&self.path);
+        if modified != self.keys.read().unwrap().0 {
+            let _ = self.reload();
+        }
+    }
+
+    /// Reloads the key file. If it can't be read, the old keys are kept rather
+    /// than locking everyone out, until the file changes again.
+    pub fn reload(&self) -> Result<(), Error> {
+        let modified = modified_time(&self.path);
+
+        match KeyFile::load(&self.path) {
+            Ok(key_file) => {
+                info!("Reloaded the key file {}", self.path.display());
+                *self.keys.write().unwrap() = (modified, key_file);
+                Ok(())
+            },
+            Err(e) => {
+                error!("Could not reload the key file {}: {}", self.path.display(), e);
+                self.keys.write().unwrap().0 = modified;
+                Err(e)
+            },
+        }
+    }
+}
+
+/// Keys are long random strings, so a plain SHA-256 is enough to keep them
+/// safe without slowing down every connection.
+pub fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+// Bots and people playing by hand get their team names tagged by the server,
+// so nobody else can use the tags
+fn is_tagged(team_name: &str) -> bool {
+    let team_name = team_name.to_uppercase();
+    team_name.starts_with(BOT_TAG) || team_name.starts_with(HUMAN_TAG)
+}
+
+fn random_string(length: usize) -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(length).collect()
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A key file of its own for every test, removed afterwards
+    struct TempKeyFile(PathBuf);
+
+    impl TempKeyFile {
+        fn new() -> Self {
+            let name = format!("tokyo-keys-{}.json", random_string(KEY_ID_LENGTH));
+            TempKeyFile(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempKeyFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn generate(key_file: &mut KeyFile, team_name: Option<&str>) -> Result<String, Error> {
+        let team_name = team_name.map(str::to_string);
+        key_file.generate(team_name, Role::Player, vec![], None, None).map(|(key, _)| key)
+    }
+
+    #[test]
+    fn keys_are_stored_as_sha256_hashes() {
+        assert_eq!(
+            hash_key("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let mut key_file = KeyFile::default();
+        let key = generate(&mut key_file, None).unwrap();
+
+        assert_eq!(key.len(), KEY_LENGTH);
+        assert_eq!(key_file.keys[0].hash, hash_key(&key));
+        assert!(!serde_json::to_string(&key_file).unwrap().contains(&key));
+        assert_eq!(key_file.find(&key).map(|api_key| &api_key.id), Some(&key_file.keys[0].id));
+        assert!(key_file.find(&key[1..]).is_none());
+    }
+
+    #[test]
+    fn team_names_can_only_be_locked_once() {
+        let mut key_file = KeyFile::default();
+        generate(&mut key_file, Some("rustaceans")).unwrap();
+
+        assert!(generate(&mut key_file, Some("rustaceans")).is_err());
+        assert_eq!(key_file.team_owner("rustaceans"), Some(key_file.keys[0].id.as_str()));
+    }
+
+    #[test]
+    fn team_names_are_locked_regardless_of_case() {
+        let mut key_file = KeyFile::default();
+        generate(&mut key_file, Some("Rustaceans")).unwrap();
+
+        assert!(generate(&mut key_file, Some("rustACEANS")).is_err());
+        assert_eq!(key_file.team_owner("RUSTACEANS"), Some(key_file.keys[0].id.as_str()));
+    }
+
+    #[test]
+    fn tagged_team_names_are_rejected() {
+        let path = TempKeyFile::new();
+        let mut key_file = KeyFile::default();
+        assert!(generate(&mut key_file, Some("[BOT] rustaceans")).is_err());
+        let unlocked = generate(&mut key_file, None).unwrap();
+        key_file.save(&path.0).unwrap();
+
+        let keys = KeyStore::open(&path.0, false).unwrap();
+        let unlocked = keys.authenticate(&unlocked).unwrap();
+        assert!(keys.team_name(&unlocked, "[HUMAN] rustaceans").is_err());
+        assert!(keys.team_name(&unlocked, "[bot] rustaceans").is_err());
+        assert_eq!(keys.team_name(&unlocked, "rustaceans [BOT]"), Ok("rustaceans [BOT]".into()));
+    }
+
+    #[test]
+    fn locked_team_names_belong_to_their_key() {
+        let path = TempKeyFile::new();
+        let mut key_file = KeyFile::default();
+        let locked = generate(&mut key_file, Some("rustaceans")).unwrap();
+        let unlocked = generate(&mut key_file, None).unwrap();
+        key_file.save(&path.0).unwrap();
+
+        let keys = KeyStore::open(&path.0, false).unwrap();
+        let locked = keys.authenticate(&locked).unwrap();
+        let unlocked = keys.authenticate(&unlocked).unwrap();
+
+        // A locked key always plays under its own name
+        assert_eq!(keys.team_name(&locked, "anything"), Ok("rustaceans".to_string()));
+        assert!(keys.team_name(&unlocked, "rustaceans").is_err());
+        assert_eq!(keys.team_name(&unlocked, "gophers"), Ok("gophers".to_string()));
+    }
+
+    #[test]
+    fn new_keys_work_after_a_reload() {
+        let path = TempKeyFile::new();
+        let keys = KeyStore::open(&path.0, false).unwrap();
+
+        let mut key_file = KeyFile::default();
+        let key = generate(&mut key_file, None).unwrap();
+        key_file.save(&path.0).unwrap();
+
+        assert!(keys.authenticate(&key).is_none());
+        keys.reload_if_changed();
+        assert!(keys.authenticate(&key).is_some());
+
+        key_file.keys.clear();
+        key_file.save(&path.0).unwrap();
+        keys.reload().unwrap();
+        assert!(keys.authenticate(&key).is_none());
+    }
+
+    #[test]
+    fn unreadable_key_files_keep_the_old_keys() {
+        let path = TempKeyFile::new();
+        let mut key_file = KeyFile::default();
+        let key = generate(&mut key_file, None).unwrap();
+        key_file.save(&path.0).unwrap();
+
+        let keys = KeyStore::open(&path.0, false).unwrap();
+        fs::write(&path.0, "not json").unwrap();
+
+        assert!(keys.reload().is_err());
+        assert!(keys.authenticate(&key).is_some());
+    }
+
+    #[test]
+    fn dev_mode_lets_unknown_keys_in() {
+        let path = TempKeyFile::new();
+        let keys = KeyStore::open(&path.0, true).unwrap();
+
+        let key = keys.authenticate("anything").unwrap();
+        assert_eq!(key.role, Role::Player);
+        assert_eq!(keys.authenticate("anything").unwrap().id, key.id);
+        assert!(KeyStore::open(&path.0, false).unwrap().authenticate("anything").is_none());
+    }
+
+    #[test]
+    fn roles_parse_from_their_names() {
+        assert_eq!("human".parse::<Role>().unwrap(), Role::Human);
+        assert_eq!("admin".parse::<Role>().unwrap(), Role::Admin);
+        assert!("root".parse::<Role>().is_err());
+    }
+}
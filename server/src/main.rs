@@ -1,5 +1,3 @@
-#![feature(drain_filter)]
-
 #[macro_use]
 extern crate log;
 
@@ -7,61 +5,299 @@ extern crate log;
 extern crate serde_derive;
 
 mod actors;
+mod bots;
+mod chat;
+mod cli;
 mod controllers;
 mod game;
+mod health;
+mod keys;
+mod map;
+mod metrics;
 mod models;
+mod ratings;
+mod recording;
+mod rooms;
+mod snapshot;
+mod tournament;
 
-use crate::actors::GameActor;
+use crate::{
+    actors::{GameActor, KeyReloadActor, LobbyActor, ShutdownActor, TournamentActor},
+    cli::{Command, ConfigOpt, HttpOpt, Opt},
+    game::GameConfig,
+    keys::KeyStore,
+    map::Map,
+    ratings::RatingStore,
+    recording::Recorder,
+    rooms::{Rooms, MAIN_ROOM},
+    snapshot::Snapshot,
+};
 use actix::{Actor, Addr, System};
 use actix_web::{http::Method, middleware::Logger, server, App};
-use dotenv::dotenv;
-use lazy_static::lazy_static;
 use listenfd::ListenFd;
-use std::collections::HashSet;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use structopt::StructOpt;
 
-#[derive(Deserialize, Debug)]
+#[derive(Default, Deserialize, Debug)]
+#[serde(default)]
 pub struct AppConfig {
     server_port: Option<u16>,
-    api_keys: HashSet<String>,
+    key_file: Option<String>,
     dev_mode: bool,
+    map_file: Option<String>,
+    snapshot_file: Option<String>,
+    tournament_file: Option<String>,
+    ratings_file: Option<String>,
+    ranked_match_size: Option<usize>,
 }
 
 pub struct AppState {
-    game_addr: Addr<GameActor>,
+    rooms: Rooms,
+    tournaments: Addr<TournamentActor>,
+    lobby: Addr<LobbyActor>,
+    keys: Arc<KeyStore>,
+    ratings: Arc<RatingStore>,
 }
 
-lazy_static! {
-    static ref APP_CONFIG: AppConfig = {
-        dotenv().expect("Could not load the .env config file");
-        envy::from_env::<AppConfig>().expect("Could not deserialize the .env config file")
-    };
+fn main() -> Result<(), String> {
+    match Opt::from_args().cmd.unwrap_or_default() {
+        Command::Serve { http, record } => serve(http, record),
+        Command::Simulate { config, players, seconds, record } => {
+            let (app_config, game_config) = load_config(&config)?;
+            let map = load_map(&app_config)?;
+            let recorder = record.map(create_recorder).transpose()?;
+
+            cli::simulate(map, game_config, players, seconds, recorder).map_err(|e| e.to_string())
+        },
+        Command::Replay { http, recording } => replay(http, recording),
+        Command::Keys { config, file, cmd } => {
+            let (app_config, _) = load_config(&config)?;
+            let file = file.unwrap_or_else(|| key_file(&app_config).into());
+
+            cli::keys(file, cmd).map_err(|e| e.to_string())
+        },
+    }
 }
 
-fn main() -> Result<(), String> {
-    lazy_static::initialize(&APP_CONFIG);
-    env_logger::init();
+// Reads the config variables from the environment, after loading them from
+// the config file. Without a config file the .env file is optional.
+fn load_config(opt: &ConfigOpt) -> Result<(AppConfig, GameConfig), String> {
+    match &opt.config {
+        Some(path) => dotenv::from_path(path)
+            .map_err(|e| format!("Could not load the config file {}: {}", path.display(), e))?,
+        None => {
+            dotenv::dotenv().ok();
+        },
+    }
+
+    // After loading the config file, so it can set RUST_LOG
+    env_logger::try_init().ok();
+
+    let app_config = envy::from_env::<AppConfig>()
+        .map_err(|e| format!("Could not read the config variables: {}", e))?;
+
+    let game_config = envy::prefixed("GAME_")
+        .from_env::<GameConfig>()
+        .map_err(|e| format!("Could not read the GAME_ config variables: {}", e))?;
+    game_config.validate()?;
+
+    Ok((app_config, game_config))
+}
+
+fn load_map(app_config: &AppConfig) -> Result<Map, String> {
+    match &app_config.map_file {
+        Some(path) => {
+            Map::load(path).map_err(|e| format!("Could not load the map file {}: {}", path, e))
+        },
+        None => Ok(Map::default()),
+    }
+}
+
+fn key_file(app_config: &AppConfig) -> String {
+    app_config.key_file.clone().unwrap_or_else(|| "keys.json".to_string())
+}
+
+fn load_keys(app_config: &AppConfig) -> Result<Arc<KeyStore>, String> {
+    let key_file = key_file(app_config);
+    let keys = KeyStore::open(&key_file, app_config.dev_mode)
+        .map_err(|e| format!("Could not load the key file {}: {}", key_file, e))?;
+
+    Ok(Arc::new(keys))
+}
+
+fn load_ratings(app_config: &AppConfig) -> Result<Arc<RatingStore>, String> {
+    let path = app_config.ratings_file.as_ref();
+    let ratings = RatingStore::open(path).map_err(|e| {
+        format!("Could not load the ratings file {}: {}", path.map_or("", String::as_str), e)
+    })?;
+
+    Ok(Arc::new(ratings))
+}
+
+fn create_recorder(path: PathBuf) -> Result<Recorder, String> {
+    Recorder::create(&path)
+        .map_err(|e| format!("Could not create the recording {}: {}", path.display(), e))
+}
+
+fn serve(http: HttpOpt, record: Option<PathBuf>) -> Result<(), String> {
+    let (app_config, game_config) = load_config(&http.config)?;
+    let map = load_map(&app_config)?;
+    let keys = load_keys(&app_config)?;
+    let ratings = load_ratings(&app_config)?;
+    let recorder = record.map(create_recorder).transpose()?;
+
+    // Continue the game from before the last shutdown, if there was one
+    let snapshot = match &app_config.snapshot_file {
+        Some(path) if Path::new(path).exists() => {
+            let snapshot = Snapshot::load(path)
+                .map_err(|e| format!("Could not load the snapshot file {}: {}", path, e))?;
+            info!("Restored the game from {}", path);
+            Some(snapshot)
+        },
+        _ => None,
+    };
+
+    let actor_system = System::new("meetup-server");
+
+    let snapshot_file = app_config.snapshot_file.clone();
+    let game_actor =
+        GameActor::new(MAIN_ROOM, map.clone(), game_config.clone(), keys.clone(), ratings.clone())
+            .with_snapshot(snapshot, snapshot_file)
+            .with_recorder(recorder);
+
+    run_server(game_actor, Some((map, game_config)), keys, ratings, &http, &app_config)?;
+    let _ = actor_system.run();
+
+    Ok(())
+}
 
-    let server_port = APP_CONFIG.server_port.unwrap_or(3000);
+fn replay(http: HttpOpt, path: PathBuf) -> Result<(), String> {
+    let (app_config, _) = load_config(&http.config)?;
+    let keys = load_keys(&app_config)?;
+    let ratings = load_ratings(&app_config)?;
+    let recording = recording::open(&path)
+        .map_err(|e| format!("Could not open the recording {}: {}", path.display(), e))?;
 
     let actor_system = System::new("meetup-server");
 
-    let game_actor = GameActor::new();
-    let game_actor_addr = game_actor.start();
+    let game_actor = GameActor::replay(MAIN_ROOM, recording, keys.clone(), ratings.clone());
+
+    run_server(game_actor, None, keys, ratings, &http, &app_config)?;
+    let _ = actor_system.run();
+
+    Ok(())
+}
+
+// Starts the main room and the HTTP server, to be run by the actor system.
+// Other rooms can only be created with a map and game config.
+fn run_server(
+    game_actor: GameActor,
+    room_template: Option<(Map, GameConfig)>,
+    keys: Arc<KeyStore>,
+    ratings: Arc<RatingStore>,
+    http: &HttpOpt,
+    app_config: &AppConfig,
+) -> Result<(), String> {
+    let static_dir = http.static_dir.clone();
+    if !static_dir.is_dir() {
+        return Err(format!("The static file directory {} doesn't exist", static_dir.display()));
+    }
+
+    let ranked_match_size = app_config.ranked_match_size.unwrap_or(4);
+    if ranked_match_size < 2 {
+        return Err("Ranked matches need at least 2 players".to_string());
+    }
+
+    let rooms = Rooms::new(game_actor.start(), room_template, keys.clone(), ratings.clone());
+    let tournament_file = app_config.tournament_file.clone();
+    let tournaments =
+        TournamentActor::new(rooms.clone(), keys.clone(), ratings.clone(), tournament_file).start();
+    let lobby =
+        LobbyActor::new(rooms.clone(), keys.clone(), ratings.clone(), ranked_match_size).start();
+    ShutdownActor::new(rooms.clone()).start();
+    KeyReloadActor::new(keys.clone()).start();
 
     let mut server = server::new(move || {
-        let app_state = AppState { game_addr: game_actor_addr.clone() };
+        let app_state = AppState {
+            rooms: rooms.clone(),
+            tournaments: tournaments.clone(),
+            lobby: lobby.clone(),
+            keys: keys.clone(),
+            ratings: ratings.clone(),
+        };
 
         App::with_state(app_state)
             .middleware(Logger::default())
             .resource("/socket", |r| {
                 r.method(Method::GET).with(controllers::api::socket_handler);
             })
+            .resource("/session", |r| {
+                r.method(Method::POST).with(controllers::api::session_handler);
+            })
             .resource("/spectate", |r| {
                 r.method(Method::GET).with(controllers::api::spectate_handler);
             })
+            .resource("/stats", |r| {
+                r.method(Method::GET).with(controllers::api::stats_handler);
+            })
+            .resource("/metrics", |r| {
+                r.method(Method::GET).with(controllers::api::metrics_handler);
+            })
+            .resource("/healthz", |r| {
+                r.method(Method::GET).with(controllers::api::healthz_handler);
+            })
+            .resource("/readyz", |r| {
+                r.method(Method::GET).with(controllers::api::readyz_handler);
+            })
+            .resource("/tournaments", |r| {
+                r.method(Method::GET).with(controllers::api::tournaments_handler);
+            })
+            .resource("/tournaments/{id}", |r| {
+                r.method(Method::GET).with(controllers::api::tournament_handler);
+            })
+            .resource("/leaderboard", |r| {
+                r.method(Method::GET).with(controllers::api::leaderboard_handler);
+            })
+            .resource("/admin/rooms", |r| {
+                r.method(Method::GET).with(controllers::admin::rooms_handler);
+            })
+            .resource("/admin/rooms/{room}", |r| {
+                r.method(Method::POST).with(controllers::admin::create_room_handler);
+                r.method(Method::DELETE).with(controllers::admin::close_room_handler);
+            })
+            .resource("/admin/rooms/{room}/players", |r| {
+                r.method(Method::GET).with(controllers::admin::players_handler);
+            })
+            .resource("/admin/rooms/{room}/bots", |r| {
+                r.method(Method::GET).with(controllers::admin::bots_handler);
+                r.method(Method::POST).with(controllers::admin::add_bot_handler);
+            })
+            .resource("/admin/rooms/{room}/bots/{player_id}", |r| {
+                r.method(Method::DELETE).with(controllers::admin::remove_bot_handler);
+            })
+            .resource("/admin/tournaments", |r| {
+                r.method(Method::POST).with(controllers::admin::create_tournament_handler);
+            })
+            .resource("/admin/tournaments/{id}", |r| {
+                r.method(Method::DELETE).with(controllers::admin::cancel_tournament_handler);
+            })
+            .resource("/admin/keys/reload", |r| {
+                r.method(Method::POST).with(controllers::admin::reload_keys_handler);
+            })
+            .resource("/admin/ratings", |r| {
+                r.method(Method::GET).with(controllers::admin::ratings_handler);
+            })
+            .resource("/admin/ratings/{key_id}", |r| {
+                r.method(Method::DELETE).with(controllers::admin::reset_rating_handler);
+            })
             .handler(
                 "/",
-                actix_web::fs::StaticFiles::new("../spectator/").unwrap().index_file("index.html"),
+                actix_web::fs::StaticFiles::new(&static_dir)
+                    .expect("The static file directory was checked at startup")
+                    .index_file("index.html"),
             )
             .resource("/{tail:.*}j", |r| {
                 r.method(Method::GET).with(controllers::common::index_handler)
@@ -71,15 +307,24 @@ fn main() -> Result<(), String> {
     // Bind to the development file descriptor if available
     // Run with: systemfd --no-pid -s http::3000 -- cargo watch -x run
     let mut listenfd = ListenFd::from_env();
-    server = if let Some(fd) = listenfd.take_tcp_listener(0).unwrap() {
+    let fd = listenfd
+        .take_tcp_listener(0)
+        .map_err(|e| format!("Could not use the development file descriptor: {}", e))?;
+
+    server = if let Some(fd) = fd {
         server.listen(fd)
     } else {
-        server.bind(format!("0.0.0.0:{}", server_port)).unwrap()
-    };
+        let bind = match &http.bind {
+            Some(bind) => bind.clone(),
+            None => format!("0.0.0.0:{}", app_config.server_port.unwrap_or(3000)),
+        };
 
-    server.start();
+        server.bind(&bind).map_err(|e| format!("Could not listen on {}: {}", bind, e))?
+    };
 
-    let _ = actor_system.run();
+    // The shutdown actor handles SIGTERM and Ctrl-C, so the games get saved
+    // before the server stops
+    server.disable_signals().start();
 
     Ok(())
 }
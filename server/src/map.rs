@@ -0,0 +1,97 @@
+use crate::game::BOUNDS;
+use failure::{format_err, Error};
+use std::{fs::File, path::Path};
+use tokyo::models::Obstacle;
+
+/// The layout of an arena. Maps are loaded from JSON files, see the `maps`
+/// folder for examples.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Map {
+    pub bounds: (f32, f32),
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    #[serde(default)]
+    pub item_spawns: Vec<(f32, f32)>,
+    #[serde(default)]
+    pub spawn_points: Vec<(f32, f32)>,
+}
+
+impl Default for Map {
+    fn default() -> Self {
+        Self { bounds: BOUNDS, obstacles: vec![], item_spawns: vec![], spawn_points: vec![] }
+    }
+}
+
+impl Map {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Map, Error> {
+        let map: Map = serde_json::from_reader(File::open(path)?)?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    /// Catches layouts the game can't be played on.
+    pub fn validate(&self) -> Result<(), Error> {
+        let (width, height) = self.bounds;
+        if !width.is_finite() || !height.is_finite() || width <= 0.0 || height <= 0.0 {
+            return Err(format_err!("The bounds have to be positive, got {:?}", self.bounds));
+        }
+
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            match obstacle {
+                Obstacle::Circle { radius, .. } if !radius.is_finite() || *radius <= 0.0 => {
+                    return Err(format_err!("Obstacle {} has a radius of {}", i, radius));
+                },
+                Obstacle::Polygon { points } if points.len() < 3 => {
+                    return Err(format_err!("Obstacle {} has fewer than 3 points", i));
+                },
+                _ => {},
+            }
+        }
+
+        let mut spawns = self.spawn_points.iter().chain(&self.item_spawns);
+        if let Some(point) =
+            spawns.find(|(x, y)| !(0.0..=width).contains(x) || !(0.0..=height).contains(y))
+        {
+            return Err(format_err!("The spawn point {:?} is outside of the bounds", point));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_map_is_valid() {
+        assert!(Map::default().validate().is_ok());
+    }
+
+    #[test]
+    fn bounds_have_to_be_positive() {
+        assert!(Map { bounds: (0.0, 100.0), ..Map::default() }.validate().is_err());
+        assert!(Map { bounds: (100.0, -1.0), ..Map::default() }.validate().is_err());
+        assert!(Map { bounds: (f32::NAN, 100.0), ..Map::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn obstacles_have_to_have_an_area() {
+        let circle = Obstacle::Circle { x: 10.0, y: 10.0, radius: 0.0 };
+        assert!(Map { obstacles: vec![circle], ..Map::default() }.validate().is_err());
+
+        let line = Obstacle::Polygon { points: vec![(0.0, 0.0), (10.0, 10.0)] };
+        assert!(Map { obstacles: vec![line], ..Map::default() }.validate().is_err());
+
+        let triangle = Obstacle::Polygon { points: vec![(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)] };
+        assert!(Map { obstacles: vec![triangle], ..Map::default() }.validate().is_ok());
+    }
+
+    #[test]
+    fn spawn_points_have_to_be_inside_the_bounds() {
+        let map = Map { bounds: (100.0, 100.0), ..Map::default() };
+        assert!(Map { spawn_points: vec![(50.0, 50.0)], ..map.clone() }.validate().is_ok());
+        assert!(Map { spawn_points: vec![(150.0, 50.0)], ..map.clone() }.validate().is_err());
+        assert!(Map { item_spawns: vec![(50.0, -1.0)], ..map }.validate().is_err());
+    }
+}
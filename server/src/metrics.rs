@@ -0,0 +1,301 @@
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+// Per key series beyond this many are folded into one `key_id="other"`
+// series, or not kept at all for gauges, so the metrics stay small no matter
+// how many keys connect
+const MAX_KEY_SERIES: usize = 256;
+const OTHER_KEYS: &str = "other";
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+/// Server health counters, exposed at `/metrics` in the Prometheus text format.
+/// Everything the game loop thread touches is a plain atomic, so recording
+/// never waits on the HTTP handlers or the actors.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    tick_count: AtomicU64,
+    tick_micros_total: AtomicU64,
+    last_tick_micros: AtomicU64,
+    tick_overruns: AtomicU64,
+    messages_in: AtomicU64,
+    messages_out: AtomicU64,
+    frames_dropped: AtomicU64,
+    serialization_count: AtomicU64,
+    serialization_micros_total: AtomicU64,
+    game_loop_queue_depth: AtomicI64,
+    // By API key ID, which unlike team names can't be picked by the players.
+    // Only updated from the websocket actors.
+    rate_limited: Mutex<HashMap<String, u64>>,
+    frames_behind: Mutex<HashMap<String, u32>>,
+    // Players and spectators, by room
+    connections: Mutex<HashMap<String, (usize, usize)>>,
+}
+
+impl Metrics {
+    pub fn record_tick(&self, duration: Duration, target: Duration) {
+        let micros = as_micros(duration);
+
+        self.tick_count.fetch_add(1, Ordering::Relaxed);
+        self.tick_micros_total.fetch_add(micros, Ordering::Relaxed);
+        self.last_tick_micros.store(micros, Ordering::Relaxed);
+
+        if duration > target {
+            self.tick_overruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// None once the room is closed.
+    pub fn set_connections(&self, room: &str, connections: Option<(usize, usize)>) {
+        let mut all_connections = self.connections.lock().unwrap();
+
+        match connections {
+            Some(connections) => {
+                all_connections.insert(room.to_string(), connections);
+            },
+            None => {
+                all_connections.remove(room);
+            },
+        }
+    }
+
+    pub fn record_message_in(&self) {
+        self.messages_in.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_message_out(&self) {
+        self.messages_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_frame_dropped(&self) {
+        self.frames_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_serialization(&self, serialization: Duration) {
+        self.serialization_count.fetch_add(1, Ordering::Relaxed);
+        self.serialization_micros_total.fetch_add(as_micros(serialization), Ordering::Relaxed);
+    }
+
+    pub fn record_rate_limited(&self, key_id: &str) {
+        let mut rate_limited = self.rate_limited.lock().unwrap();
+
+        let tracked = rate_limited.contains_key(key_id) || rate_limited.len() < MAX_KEY_SERIES - 1;
+        let series = if tracked { key_id } else { OTHER_KEYS };
+        *rate_limited.entry(series.to_string()).or_default() += 1;
+    }
+
+    /// How many state frames the player's socket skipped while writing out the
+    /// current one. None once they disconnect.
+    pub fn set_frames_behind(&self, key_id: &str, frames_behind: Option<u32>) {
+        let mut all_frames_behind = self.frames_behind.lock().unwrap();
+
+        match frames_behind {
+            Some(frames_behind) => {
+                if all_frames_behind.contains_key(key_id)
+                    || all_frames_behind.len() < MAX_KEY_SERIES
+                {
+                    all_frames_behind.insert(key_id.to_string(), frames_behind);
+                }
+            },
+            None => {
+                all_frames_behind.remove(key_id);
+            },
+        }
+    }
+
+    pub fn command_queued(&self) {
+        self.game_loop_queue_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn command_dequeued(&self) {
+        self.game_loop_queue_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Commands that were queued for a game loop which stopped before
+    /// picking them up.
+    pub fn commands_dropped(&self, count: i64) {
+        self.game_loop_queue_depth.fetch_sub(count, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let tick_count = self.tick_count.load(Ordering::Relaxed);
+        let tick_total = self.tick_micros_total.load(Ordering::Relaxed);
+        summary(
+            &mut out,
+            "tokyo_tick_duration_seconds",
+            "Time spent simulating a game tick.",
+            tick_count,
+            tick_total,
+        );
+        gauge(
+            &mut out,
+            "tokyo_last_tick_duration_seconds",
+            "Time spent simulating the last game tick.",
+            seconds(self.last_tick_micros.load(Ordering::Relaxed)),
+        );
+        counter(
+            &mut out,
+            "tokyo_tick_overruns_total",
+            "Ticks which took longer than the tick interval.",
+            self.tick_overruns.load(Ordering::Relaxed),
+        );
+
+        let name = "tokyo_connected_players";
+        header(&mut out, name, "Players connected over a websocket, by room.", "gauge");
+        for (room, (players, _)) in self.connections.lock().unwrap().iter() {
+            let _ = writeln!(out, "{}{{room=\"{}\"}} {}", name, escape(room), players);
+        }
+
+        let name = "tokyo_connected_spectators";
+        header(&mut out, name, "Spectators connected over a websocket, by room.", "gauge");
+        for (room, (_, spectators)) in self.connections.lock().unwrap().iter() {
+            let _ = writeln!(out, "{}{{room=\"{}\"}} {}", name, escape(room), spectators);
+        }
+
+        counter(
+            &mut out,
+            "tokyo_messages_in_total",
+            "Websocket messages received from clients.",
+            self.messages_in.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "tokyo_messages_out_total",
+            "Websocket messages sent to clients and spectators.",
+            self.messages_out.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "tokyo_frames_dropped_total",
+            "Game state frames skipped because a socket was still busy sending an older one.",
+            self.frames_dropped.load(Ordering::Relaxed),
+        );
+
+        let serialization_count = self.serialization_count.load(Ordering::Relaxed);
+        let serialization_total = self.serialization_micros_total.load(Ordering::Relaxed);
+        summary(
+            &mut out,
+            "tokyo_serialization_duration_seconds",
+            "Time spent serializing outgoing messages.",
+            serialization_count,
+            serialization_total,
+        );
+
+        gauge(
+            &mut out,
+            "tokyo_game_loop_queue_depth",
+            "Commands waiting to be picked up by the game loop.",
+            self.game_loop_queue_depth.load(Ordering::Relaxed) as f64,
+        );
+
+        let name = "tokyo_rate_limited_total";
+        header(&mut out, name, "Commands dropped by the rate limiter, by API key ID.", "counter");
+        for (key_id, count) in self.rate_limited.lock().unwrap().iter() {
+            let _ = writeln!(out, "{}{{key_id=\"{}\"}} {}", name, escape(key_id), count);
+        }
+
+        let name = "tokyo_frames_behind";
+        header(&mut out, name, "State frames a player's socket is behind, by API key ID.", "gauge");
+        for (key_id, frames_behind) in self.frames_behind.lock().unwrap().iter() {
+            let _ = writeln!(out, "{}{{key_id=\"{}\"}} {}", name, escape(key_id), frames_behind);
+        }
+
+        out
+    }
+}
+
+fn as_micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+fn seconds(micros: u64) -> f64 {
+    micros as f64 / 1e6
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn summary(out: &mut String, name: &str, help: &str, count: u64, micros_total: u64) {
+    header(out, name, help, "summary");
+    let _ = writeln!(out, "{}_sum {}\n{}_count {}", name, seconds(micros_total), name, count);
+}
+
+// Label values can't contain raw quotes, backslashes or newlines
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limits_are_counted_by_key_id() {
+        let metrics = Metrics::default();
+        metrics.record_rate_limited("abc");
+        metrics.record_rate_limited("abc");
+
+        assert!(metrics.render().contains("tokyo_rate_limited_total{key_id=\"abc\"} 2\n"));
+    }
+
+    #[test]
+    fn rate_limits_beyond_the_cap_are_folded_together() {
+        let metrics = Metrics::default();
+        for i in 0..MAX_KEY_SERIES + 10 {
+            metrics.record_rate_limited(&format!("key-{}", i));
+        }
+        metrics.record_rate_limited("key-0");
+
+        let rate_limited = metrics.rate_limited.lock().unwrap();
+        assert_eq!(rate_limited.len(), MAX_KEY_SERIES);
+        assert_eq!(rate_limited["key-0"], 2);
+        assert_eq!(rate_limited[OTHER_KEYS], 11);
+    }
+
+    #[test]
+    fn frames_behind_are_capped_and_removed_on_disconnect() {
+        let metrics = Metrics::default();
+        for i in 0..MAX_KEY_SERIES + 10 {
+            metrics.set_frames_behind(&format!("key-{}", i), Some(3));
+        }
+        assert_eq!(metrics.frames_behind.lock().unwrap().len(), MAX_KEY_SERIES);
+
+        metrics.set_frames_behind("key-0", None);
+        assert!(!metrics.frames_behind.lock().unwrap().contains_key("key-0"));
+    }
+
+    #[test]
+    fn dropped_commands_leave_the_queue() {
+        let metrics = Metrics::default();
+        for _ in 0..5 {
+            metrics.command_queued();
+        }
+        metrics.command_dequeued();
+        metrics.commands_dropped(4);
+
+        assert!(metrics.render().contains("tokyo_game_loop_queue_depth 0\n"));
+    }
+}
@@ -1,5 +1,11 @@
+use crate::{
+    bots::BotConfig,
+    keys::ApiKey,
+    tournament::{RoomResult, TournamentConfig},
+};
 use actix::Message;
-use tokyo::models::GameCommand;
+use std::{collections::HashMap, sync::Arc};
+use tokyo::models::{ChatMessage, GameCommand, GameState, PlayerStats, Rejection, Tournament};
 
 #[derive(Debug, Message)]
 pub struct PlayerGameCommand {
@@ -7,5 +13,200 @@ pub struct PlayerGameCommand {
     pub cmd: GameCommand,
 }
 
+/// How a socket proves who it is.
+#[derive(Clone, Debug)]
+pub enum Credentials {
+    ApiKey(ApiKey),
+    // A token from an earlier connection or from `/session`, and the API key
+    // from the header to fall back on when the token isn't good anymore
+    Session(String, Option<ApiKey>),
+    Spectator,
+}
+
+/// The first message of a socket which didn't send its API key in a header.
+#[derive(Debug, Deserialize)]
+pub struct AuthMessage {
+    pub auth: String,
+}
+
+#[derive(Debug, Message)]
+pub struct Authenticated {
+    pub key: ApiKey,
+    pub team_name: String,
+    pub token: String,
+}
+
+#[derive(Debug, Message)]
+pub struct AuthFailed {
+    pub reason: String,
+}
+
+/// Tells a socket in the ranked queue that it's waiting for a match.
+#[derive(Debug, Message)]
+pub struct Queued {
+    pub key_id: String,
+    pub team_name: String,
+}
+
+/// Sends a socket's client to another room, which it has to reconnect to.
+#[derive(Debug, Message)]
+pub struct Redirect {
+    pub room: String,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "String")]
+pub struct CreateSession {
+    pub key: ApiKey,
+}
+
+#[derive(Debug, Message)]
+pub struct Heartbeat {
+    pub api_key: String,
+}
+
+/// How many state frames a player's socket skipped because it couldn't keep
+/// up, whenever that changes.
+#[derive(Debug, Message)]
+pub struct FramesBehind {
+    pub api_key: String,
+    pub frames_behind: u32,
+}
+
 #[derive(Debug, Message)]
 pub struct ClientStop {}
+
+#[derive(Debug, Message)]
+pub struct ServerRestarting {}
+
+/// Tells a room the server is stopping. It lets everybody know, and saves the
+/// game if it keeps a snapshot.
+#[derive(Debug, Message)]
+pub struct ShutDown;
+
+/// A `ServerToClient::GameState` message, serialized once by the game loop and
+/// shared by every socket.
+#[derive(Clone, Debug, Message)]
+pub struct StateFrame {
+    pub json: Arc<String>,
+}
+
+/// The game as of the last stats update, to restart the game loop from if it
+/// crashes.
+#[derive(Debug, Message)]
+pub struct Checkpoint {
+    pub game_state: GameState,
+    pub stats: HashMap<u32, PlayerStats>,
+}
+
+#[derive(Debug, Message)]
+pub struct CommandRejected {
+    pub player_id: u32,
+    pub rejection: Rejection,
+}
+
+/// Team names from a recording being replayed.
+#[derive(Debug, Message)]
+pub struct TeamNamesUpdate {
+    pub team_names: HashMap<u32, String>,
+}
+
+/// Something a player said, from a recording being replayed.
+#[derive(Debug, Message)]
+pub struct ChatUpdate {
+    pub message: ChatMessage,
+}
+
+#[derive(Debug, Message)]
+pub struct StatsUpdate {
+    pub stats: HashMap<u32, PlayerStats>,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "HashMap<u32, TeamStats>")]
+pub struct GetStats;
+
+#[derive(Debug, Serialize)]
+pub struct TeamStats {
+    pub team_name: String,
+    #[serde(flatten)]
+    pub stats: PlayerStats,
+}
+
+/// Stops a room's game and disconnects everybody in it.
+#[derive(Debug, Message)]
+pub struct CloseRoom;
+
+#[derive(Debug, Message)]
+#[rtype(result = "BotSummary")]
+pub struct AddBot {
+    pub config: BotConfig,
+}
+
+/// Whether there was a bot with that player ID.
+#[derive(Debug, Message)]
+#[rtype(result = "bool")]
+pub struct RemoveBot {
+    pub player_id: u32,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<BotSummary>")]
+pub struct ListBots;
+
+/// The players connected to a room, for admins.
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<PlayerSummary>")]
+pub struct ListPlayers;
+
+#[derive(Debug, Serialize)]
+pub struct PlayerSummary {
+    pub player_id: u32,
+    pub team_name: String,
+    pub key_id: String,
+    pub frames_behind: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BotSummary {
+    pub player_id: u32,
+    pub team_name: String,
+    #[serde(flatten)]
+    pub config: BotConfig,
+}
+
+/// Sends the players with these API key IDs, in whichever room they're
+/// connected to, to another room.
+#[derive(Clone, Debug, Message)]
+pub struct RedirectPlayers {
+    pub room: String,
+    pub key_ids: Vec<String>,
+}
+
+/// The bracket of a tournament, for spectators in every room.
+#[derive(Clone, Debug, Message)]
+pub struct TournamentUpdate {
+    pub tournament: Tournament,
+}
+
+/// How every player who joined a room has done so far.
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<RoomResult>")]
+pub struct GetStandings;
+
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Tournament, String>")]
+pub struct CreateTournament {
+    pub config: TournamentConfig,
+}
+
+/// Whether there was a running tournament with that ID.
+#[derive(Debug, Message)]
+#[rtype(result = "bool")]
+pub struct CancelTournament {
+    pub id: u32,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<Tournament>")]
+pub struct GetTournaments;
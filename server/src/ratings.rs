@@ -0,0 +1,229 @@
+use failure::Error;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+/// What every API key starts out with.
+pub const INITIAL_RATING: f64 = 1500.0;
+
+// How far a single game can move a rating, split between the opponents
+const K_FACTOR: f64 = 32.0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rating {
+    pub rating: f64,
+    pub games: u32,
+    /// The team name the key last played a rated game as.
+    pub team_name: String,
+}
+
+/// How one player did in a rated game. Players are ranked by points, then by
+/// kills.
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub key_id: String,
+    pub team_name: String,
+    pub points: u32,
+    pub kills: u32,
+}
+
+/// Elo ratings by API key ID, for free-for-all rounds and tournament
+/// matches. Every game counts as a one on one game against each of the
+/// other players, with the K factor split between them.
+#[derive(Debug)]
+pub struct RatingStore {
+    path: Option<PathBuf>,
+    ratings: RwLock<HashMap<String, Rating>>,
+}
+
+impl RatingStore {
+    /// Without a path, ratings only last until the server stops. A missing
+    /// file is treated as an empty one.
+    pub fn open<P: Into<PathBuf>>(path: Option<P>) -> Result<RatingStore, Error> {
+        let path = path.map(Into::into);
+        let ratings = match &path {
+            Some(path) if path.exists() => serde_json::from_reader(File::open(path)?)?,
+            _ => HashMap::new(),
+        };
+
+        Ok(RatingStore { path, ratings: RwLock::new(ratings) })
+    }
+
+    pub fn rating(&self, key_id: &str) -> f64 {
+        self.ratings.read().unwrap().get(key_id).map_or(INITIAL_RATING, |rating| rating.rating)
+    }
+
+    pub fn all(&self) -> HashMap<String, Rating> {
+        self.ratings.read().unwrap().clone()
+    }
+
+    /// Updates everybody's rating after a game. Games with fewer than two
+    /// players don't count.
+    pub fn record(&self, outcomes: &[Outcome]) {
+        if outcomes.len() < 2 {
+            return;
+        }
+
+        {
+            let mut ratings = self.ratings.write().unwrap();
+            let before: Vec<f64> = outcomes
+                .iter()
+                .map(|o| ratings.get(&o.key_id).map_or(INITIAL_RATING, |rating| rating.rating))
+                .collect();
+
+            let k = K_FACTOR / (outcomes.len() - 1) as f64;
+            for (i, outcome) in outcomes.iter().enumerate() {
+                let change: f64 = outcomes
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(j, other)| {
+                        let expected = 1.0 / (1.0 + 10f64.powf((before[j] - before[i]) / 400.0));
+                        k * (score(outcome, other) - expected)
+                    })
+                    .sum();
+
+                let rating = ratings.entry(outcome.key_id.clone()).or_insert_with(|| Rating {
+                    rating: INITIAL_RATING,
+                    games: 0,
+                    team_name: String::new(),
+                });
+                rating.rating = before[i] + change;
+                rating.games += 1;
+                rating.team_name = outcome.team_name.clone();
+            }
+        }
+
+        self.save();
+    }
+
+    /// Starts a key over from the initial rating.
+    pub fn reset(&self, key_id: &str) -> bool {
+        let removed = self.ratings.write().unwrap().remove(key_id).is_some();
+        if removed {
+            self.save();
+        }
+
+        removed
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            if let Err(e) = save(path, &self.ratings.read().unwrap()) {
+                error!("Could not save the ratings file {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+// 1 for a win against the other player, 0.5 for a tie and 0 for a loss
+fn score(outcome: &Outcome, other: &Outcome) -> f64 {
+    match (outcome.points, outcome.kills).cmp(&(other.points, other.kills)) {
+        Ordering::Greater => 1.0,
+        Ordering::Equal => 0.5,
+        Ordering::Less => 0.0,
+    }
+}
+
+fn save(path: &Path, ratings: &HashMap<String, Rating>) -> Result<(), Error> {
+    let tmp_path = path.with_extension("tmp");
+    serde_json::to_writer_pretty(File::create(&tmp_path)?, ratings)?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(key_id: &str, points: u32, kills: u32) -> Outcome {
+        Outcome { key_id: key_id.to_string(), team_name: key_id.to_string(), points, kills }
+    }
+
+    fn total(ratings: &RatingStore) -> f64 {
+        ratings.all().values().map(|rating| rating.rating).sum()
+    }
+
+    #[test]
+    fn winner_gains_what_the_loser_loses() {
+        let ratings = RatingStore::open(None::<PathBuf>).unwrap();
+        ratings.record(&[outcome("a", 10, 1), outcome("b", 5, 3)]);
+
+        assert!(ratings.rating("a") > INITIAL_RATING);
+        assert!(ratings.rating("b") < INITIAL_RATING);
+        assert!((ratings.rating("a") - INITIAL_RATING - K_FACTOR / 2.0).abs() < 1e-9);
+        assert!((total(&ratings) - 2.0 * INITIAL_RATING).abs() < 1e-9);
+    }
+
+    #[test]
+    fn total_rating_stays_the_same() {
+        let ratings = RatingStore::open(None::<PathBuf>).unwrap();
+        ratings.record(&[outcome("a", 10, 0), outcome("b", 5, 0), outcome("c", 5, 2)]);
+        ratings.record(&[outcome("b", 3, 0), outcome("c", 8, 0)]);
+        ratings.record(&[outcome("a", 0, 0), outcome("b", 0, 0), outcome("c", 9, 9)]);
+
+        assert_eq!(ratings.all().len(), 3);
+        assert!((total(&ratings) - 3.0 * INITIAL_RATING).abs() < 1e-9);
+    }
+
+    #[test]
+    fn underdogs_gain_more_for_a_win() {
+        let ratings = RatingStore::open(None::<PathBuf>).unwrap();
+        ratings.record(&[outcome("a", 10, 0), outcome("b", 0, 0)]);
+        let favourite_win = ratings.rating("a") - (INITIAL_RATING + K_FACTOR / 2.0);
+        assert!(favourite_win.abs() < 1e-9);
+
+        let before = ratings.rating("b");
+        ratings.record(&[outcome("a", 0, 0), outcome("b", 10, 0)]);
+        assert!(ratings.rating("b") - before > K_FACTOR / 2.0);
+    }
+
+    #[test]
+    fn ties_are_decided_by_kills_then_count_as_draws() {
+        let ratings = RatingStore::open(None::<PathBuf>).unwrap();
+        ratings.record(&[outcome("a", 5, 2), outcome("b", 5, 1)]);
+        assert!(ratings.rating("a") > ratings.rating("b"));
+
+        let ratings = RatingStore::open(None::<PathBuf>).unwrap();
+        ratings.record(&[outcome("a", 5, 1), outcome("b", 5, 1)]);
+        assert_eq!(ratings.rating("a"), INITIAL_RATING);
+        assert_eq!(ratings.rating("b"), INITIAL_RATING);
+    }
+
+    #[test]
+    fn games_need_two_players() {
+        let ratings = RatingStore::open(None::<PathBuf>).unwrap();
+        ratings.record(&[outcome("a", 10, 0)]);
+        assert!(ratings.all().is_empty());
+    }
+
+    #[test]
+    fn ratings_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("tokyo-ratings-{}.json", std::process::id()));
+
+        let ratings = RatingStore::open(Some(&path)).unwrap();
+        ratings.record(&[outcome("a", 10, 0), outcome("b", 0, 0)]);
+        ratings.record(&[outcome("a", 10, 0), outcome("b", 0, 0)]);
+        let before = ratings.all();
+
+        let reopened = RatingStore::open(Some(&path)).unwrap();
+        let after = reopened.all();
+
+        assert_eq!(after.len(), 2);
+        for (key_id, rating) in &before {
+            assert_eq!(after[key_id].rating, rating.rating);
+            assert_eq!(after[key_id].games, 2);
+            assert_eq!(after[key_id].team_name, rating.team_name);
+        }
+
+        assert!(reopened.reset("a"));
+        assert!(!reopened.reset("a"));
+        assert_eq!(reopened.rating("a"), INITIAL_RATING);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,45 @@
+use failure::Error;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Lines, Write},
+    path::Path,
+};
+use tokyo::models::ServerToClient;
+
+/// Writes what spectators see to a file, one `ServerToClient` JSON message per
+/// line, so the game can be watched again with `tokyo-server replay`.
+#[derive(Debug)]
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Recorder, Error> {
+        Ok(Recorder { writer: BufWriter::new(File::create(path)?) })
+    }
+
+    pub fn write(&mut self, msg: &ServerToClient) {
+        let json = serde_json::to_string(msg).expect("Messages should always be serializable");
+        self.write_json(&json);
+    }
+
+    /// For messages which are already serialized, like state frames.
+    pub fn write_json(&mut self, json: &str) {
+        if let Err(e) = writeln!(self.writer, "{}", json) {
+            error!("Could not write to the recording: {}", e);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            error!("Could not write to the recording: {}", e);
+        }
+    }
+}
+
+/// The lines of a recording made by `Recorder`, read as they're replayed.
+pub type Recording = Lines<BufReader<File>>;
+
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Recording, Error> {
+    Ok(BufReader::new(File::open(path)?).lines())
+}
@@ -0,0 +1,123 @@
+use crate::{
+    actors::GameActor, game::GameConfig, keys::KeyStore, map::Map, models::messages::CloseRoom,
+    ratings::RatingStore,
+};
+use actix::{Actor, Addr};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+/// The room clients end up in when they don't ask for one. It's started with
+/// the server, keeps its snapshot across restarts, and can't be closed.
+pub const MAIN_ROOM: &str = "main";
+
+// Each room runs its own game loop thread
+const MAX_ROOMS: usize = 16;
+const MAX_ROOM_NAME_LENGTH: usize = 32;
+
+/// The game actors of every room, shared between the HTTP workers. Rooms
+/// other than the main one are created by admins, and start with an empty
+/// game on the server's map and game config.
+#[derive(Clone, Debug)]
+pub struct Rooms {
+    addrs: Arc<Mutex<HashMap<String, Addr<GameActor>>>>,
+    // Not set when replaying, which only has the main room
+    template: Option<Arc<(Map, GameConfig)>>,
+    keys: Arc<KeyStore>,
+    ratings: Arc<RatingStore>,
+}
+
+impl Rooms {
+    pub fn new(
+        main_room: Addr<GameActor>,
+        template: Option<(Map, GameConfig)>,
+        keys: Arc<KeyStore>,
+        ratings: Arc<RatingStore>,
+    ) -> Rooms {
+        let mut addrs = HashMap::new();
+        addrs.insert(MAIN_ROOM.to_string(), main_room);
+
+        Rooms {
+            addrs: Arc::new(Mutex::new(addrs)),
+            template: template.map(Arc::new),
+            keys,
+            ratings,
+        }
+    }
+
+    pub fn get(&self, room: &str) -> Option<Addr<GameActor>> {
+        self.addrs.lock().unwrap().get(room).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Addr<GameActor>> {
+        self.addrs.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.addrs.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.template.is_none()
+    }
+
+    /// Starts a game actor for a new room, on the current arbiter. With
+    /// `allowed_keys`, only those API key IDs can play in it.
+    pub fn create(
+        &self,
+        room: &str,
+        allowed_keys: Option<HashSet<String>>,
+    ) -> Result<Addr<GameActor>, String> {
+        let template = match &self.template {
+            Some(template) => template,
+            None => return Err("This server is replaying a recorded game".to_string()),
+        };
+
+        let valid_name = !room.is_empty()
+            && room.len() <= MAX_ROOM_NAME_LENGTH
+            && room.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(format!(
+                "Room names can only have up to {} letters, digits, - and _",
+                MAX_ROOM_NAME_LENGTH
+            ));
+        }
+
+        let mut addrs = self.addrs.lock().unwrap();
+        if addrs.contains_key(room) {
+            return Err(format!("The room {} already exists", room));
+        }
+        if addrs.len() >= MAX_ROOMS {
+            return Err(format!("There can't be more than {} rooms", MAX_ROOMS));
+        }
+
+        let (map, game_config) = &**template;
+        let (keys, ratings) = (self.keys.clone(), self.ratings.clone());
+        let addr = GameActor::new(room, map.clone(), game_config.clone(), keys, ratings)
+            .with_allowed_keys(allowed_keys)
+            .start();
+        addrs.insert(room.to_string(), addr.clone());
+
+        info!("Opened room {}", room);
+        Ok(addr)
+    }
+
+    /// Stops the room's game and disconnects everybody in it.
+    pub fn close(&self, room: &str) -> Result<(), String> {
+        if room == MAIN_ROOM {
+            return Err("The main room can't be closed".to_string());
+        }
+
+        match self.addrs.lock().unwrap().remove(room) {
+            Some(addr) => {
+                addr.do_send(CloseRoom);
+                info!("Closed room {}", room);
+                Ok(())
+            },
+            None => Err(format!("There is no room {}", room)),
+        }
+    }
+}
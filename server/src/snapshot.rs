@@ -0,0 +1,107 @@
+use crate::bots::BotConfig;
+use failure::Error;
+use std::{collections::HashMap, fs, fs::File, path::Path};
+use tokyo::models::{GameState, PlayerStats};
+
+/// Everything needed to pick up a game where it was left off when the server
+/// was shut down, written as JSON.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub game_state: GameState,
+    pub stats: HashMap<u32, PlayerStats>,
+    pub api_key_to_player_id: HashMap<String, u32>,
+    pub team_names: HashMap<u32, String>,
+    pub player_id_counter: u32,
+    #[serde(default)]
+    pub bots: HashMap<u32, BotConfig>,
+}
+
+impl Snapshot {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot, Error> {
+        let snapshot = serde_json::from_reader(File::open(path)?)?;
+        Ok(snapshot)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        // Write next to the old snapshot first, so a failed write doesn't
+        // destroy it
+        let tmp_path = path.as_ref().with_extension("tmp");
+        serde_json::to_writer(File::create(&tmp_path)?, self)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bots::{BotKind, Difficulty},
+        game::Game,
+    };
+    use tokyo::models::PlayerStatus;
+
+    fn snapshot_of(game: &Game) -> Snapshot {
+        Snapshot {
+            game_state: game.state.clone(),
+            stats: game.stats(),
+            api_key_to_player_id: vec![("key".to_string(), 1)].into_iter().collect(),
+            team_names: vec![(1, "rustaceans".to_string())].into_iter().collect(),
+            player_id_counter: 3,
+            bots: vec![(2, BotConfig { kind: BotKind::Chaser, difficulty: Difficulty::Hard })]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn restored_game_picks_up_where_it_left_off() {
+        let mut game = Game::default();
+        game.add_player(1);
+        game.add_player(2);
+        game.state.tick = 42;
+        game.state.scoreboard.insert(1, 7);
+
+        let path = std::env::temp_dir().join(format!("tokyo-snapshot-{}.json", std::process::id()));
+        snapshot_of(&game).save(&path).unwrap();
+        let snapshot = Snapshot::load(&path);
+        let _ = fs::remove_file(&path);
+        let snapshot = snapshot.unwrap();
+
+        assert_eq!(snapshot.api_key_to_player_id["key"], 1);
+        assert_eq!(snapshot.team_names[&1], "rustaceans");
+        assert_eq!(snapshot.player_id_counter, 3);
+        assert_eq!(snapshot.bots[&2].difficulty, Difficulty::Hard);
+
+        let mut restored = Game::default();
+        restored.restore(snapshot.game_state, snapshot.stats);
+        assert_eq!(restored.state.tick, 42);
+        assert_eq!(restored.state.scoreboard[&1], 7);
+        assert_eq!(restored.stats().len(), game.stats().len());
+
+        let positions = |game: &Game| -> Vec<(u32, f32, f32)> {
+            game.state.players.iter().map(|p| (p.id, p.x, p.y)).collect()
+        };
+        assert_eq!(positions(&restored), positions(&game));
+
+        // Nobody is connected until they come back, and then they carry on
+        // from the same spot instead of respawning
+        restored.tick(0.0);
+        assert!(restored.state.players.iter().all(|p| p.status == PlayerStatus::Disconnected));
+
+        restored.add_player(1);
+        restored.tick(0.0);
+        let player = restored.state.players.iter().find(|p| p.id == 1).unwrap();
+        assert_eq!(player.status, PlayerStatus::Active);
+        assert_eq!((player.x, player.y), (positions(&game)[0].1, positions(&game)[0].2));
+    }
+
+    #[test]
+    fn older_snapshots_without_bots_still_load() {
+        let mut value = serde_json::to_value(snapshot_of(&Game::default())).unwrap();
+        value.as_object_mut().unwrap().remove("bots");
+
+        let snapshot: Snapshot = serde_json::from_value(value).unwrap();
+        assert!(snapshot.bots.is_empty());
+    }
+}
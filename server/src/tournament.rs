@@ -0,0 +1,598 @@
+use crate::ratings::Outcome;
+use failure::Error;
+use std::{
+    collections::HashMap,
+    fs,
+    fs::File,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+use tokyo::models::{
+    MatchResult, MatchStatus, Standing, Tournament, TournamentFormat, TournamentMatch,
+    TournamentStatus,
+};
+
+const MAX_MATCH_SIZE: usize = 8;
+const MIN_ROUND_SECS: u64 = 10;
+const MAX_ROUND_SECS: u64 = 60 * 60;
+
+/// What an admin sends to start a tournament.
+#[derive(Debug, Deserialize)]
+pub struct TournamentConfig {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub format: TournamentFormat,
+    /// API key IDs in seeding order, every player key if empty.
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// Players per elimination match, round robin matches are always one on
+    /// one.
+    #[serde(default = "default_match_size")]
+    pub match_size: usize,
+    #[serde(default = "default_round_secs")]
+    pub round_secs: u64,
+}
+
+fn default_match_size() -> usize {
+    2
+}
+
+fn default_round_secs() -> u64 {
+    120
+}
+
+/// A player in a tournament, as an API key ID and the name shown in the
+/// bracket.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entrant {
+    pub key_id: String,
+    pub team_name: String,
+}
+
+/// How a player did in a match room, as reported by its game actor.
+#[derive(Clone, Debug)]
+pub struct RoomResult {
+    pub key_id: String,
+    pub points: u32,
+    pub kills: u32,
+    pub deaths: u32,
+}
+
+/// The bracket as seen by spectators, along with what the server needs to
+/// run it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TournamentRun {
+    pub tournament: Tournament,
+    entrants: Vec<Entrant>,
+    match_size: usize,
+}
+
+impl TournamentRun {
+    pub fn new(
+        id: u32,
+        config: &TournamentConfig,
+        entrants: Vec<Entrant>,
+    ) -> Result<TournamentRun, String> {
+        if entrants.len() < 2 {
+            return Err("A tournament needs at least 2 players".to_string());
+        }
+        if config.match_size < 2 || config.match_size > MAX_MATCH_SIZE {
+            return Err(format!("Matches can have between 2 and {} players", MAX_MATCH_SIZE));
+        }
+        if config.round_secs < MIN_ROUND_SECS || config.round_secs > MAX_ROUND_SECS {
+            return Err(format!(
+                "Rounds can last between {} and {} seconds",
+                MIN_ROUND_SECS, MAX_ROUND_SECS
+            ));
+        }
+
+        // Results are matched up by team name
+        for (i, entrant) in entrants.iter().enumerate() {
+            if entrants[..i].iter().any(|other| other.team_name == entrant.team_name) {
+                return Err(format!("More than one player is called {}", entrant.team_name));
+            }
+        }
+
+        let team_names: Vec<String> = entrants.iter().map(|e| e.team_name.clone()).collect();
+
+        let rounds = match config.format {
+            TournamentFormat::RoundRobin => round_robin(id, &team_names),
+            TournamentFormat::Elimination => {
+                vec![elimination_round(id, 0, &team_names, config.match_size)]
+            },
+        };
+
+        let tournament = Tournament {
+            id,
+            name: config.name.clone().unwrap_or_else(|| format!("Tournament {}", id)),
+            format: config.format,
+            status: TournamentStatus::Running,
+            round_secs: config.round_secs,
+            entrants: team_names,
+            rounds,
+            current_round: 0,
+            standings: vec![],
+            winner: None,
+        };
+
+        let mut run = TournamentRun { tournament, entrants, match_size: config.match_size };
+        run.update_standings();
+        Ok(run)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.tournament.id
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.tournament.status == TournamentStatus::Running
+    }
+
+    /// Marks the matches of the current round as being played, and returns
+    /// their rooms along with the API key IDs allowed in each. Byes are
+    /// finished right away.
+    pub fn start_round(&mut self, now: SystemTime) -> Vec<(String, Vec<String>)> {
+        let ends = now + Duration::from_secs(self.tournament.round_secs);
+        let round = self.tournament.current_round;
+        let entrants = &self.entrants;
+
+        let mut rooms = vec![];
+        for m in &mut self.tournament.rounds[round] {
+            if m.players.len() < 2 {
+                m.status = MatchStatus::Finished;
+                m.results = m.players.iter().map(|name| empty_result(name)).collect();
+                continue;
+            }
+
+            m.status = MatchStatus::Playing;
+            m.ends = Some(ends);
+
+            let key_ids = m
+                .players
+                .iter()
+                .filter_map(|name| entrants.iter().find(|e| &e.team_name == name))
+                .map(|entrant| entrant.key_id.clone())
+                .collect();
+            rooms.push((m.room.clone(), key_ids));
+        }
+
+        rooms
+    }
+
+    /// The rooms of the matches currently being played.
+    pub fn playing_rooms(&self) -> Vec<String> {
+        self.tournament
+            .rounds
+            .get(self.tournament.current_round)
+            .into_iter()
+            .flatten()
+            .filter(|m| m.status == MatchStatus::Playing)
+            .map(|m| m.room.clone())
+            .collect()
+    }
+
+    /// Ends the current round with how everybody did in each room. Players
+    /// missing from a room's results never showed up, and score nothing.
+    /// Returns whether there's another round to play.
+    pub fn finish_round(&mut self, room_results: HashMap<String, Vec<RoomResult>>) -> bool {
+        let round = self.tournament.current_round;
+        let entrants = &self.entrants;
+
+        for m in &mut self.tournament.rounds[round] {
+            if m.status != MatchStatus::Playing {
+                continue;
+            }
+
+            let room_results = room_results.get(&m.room).map(Vec::as_slice).unwrap_or(&[]);
+            let mut results: Vec<MatchResult> = m
+                .players
+                .iter()
+                .map(|name| {
+                    let key_id = entrants.iter().find(|e| &e.team_name == name).map(|e| &e.key_id);
+                    match room_results.iter().find(|result| Some(&result.key_id) == key_id) {
+                        Some(result) => MatchResult {
+                            team_name: name.clone(),
+                            points: result.points,
+                            kills: result.kills,
+                            deaths: result.deaths,
+                        },
+                        None => empty_result(name),
+                    }
+                })
+                .collect();
+
+            // Stable, so ties go to the better seed
+            results.sort_by(|a, b| {
+                b.points.cmp(&a.points).then(b.kills.cmp(&a.kills)).then(a.deaths.cmp(&b.deaths))
+            });
+
+            m.status = MatchStatus::Finished;
+            m.ends = None;
+            m.results = results;
+        }
+
+        self.update_standings();
+
+        let next_round = round + 1;
+        match self.tournament.format {
+            TournamentFormat::RoundRobin if next_round < self.tournament.rounds.len() => {
+                self.tournament.current_round = next_round;
+                true
+            },
+            TournamentFormat::RoundRobin => {
+                self.finish(self.tournament.standings.first().map(|s| s.team_name.clone()));
+                false
+            },
+            TournamentFormat::Elimination => {
+                let winners: Vec<String> = self.tournament.rounds[round]
+                    .iter()
+                    .filter_map(|m| m.results.first().map(|result| result.team_name.clone()))
+                    .collect();
+
+                if winners.len() > 1 {
+                    let id = self.tournament.id;
+                    let next = elimination_round(id, next_round, &winners, self.match_size);
+                    self.tournament.rounds.push(next);
+                    self.tournament.current_round = next_round;
+                    true
+                } else {
+                    self.finish(winners.into_iter().next());
+                    false
+                }
+            },
+        }
+    }
+
+    /// The results of the round's matches, for rating the players.
+    pub fn outcomes(&self, round: usize) -> Vec<Vec<Outcome>> {
+        let entrants = &self.entrants;
+
+        self.tournament.rounds[round]
+            .iter()
+            .map(|m| {
+                m.results
+                    .iter()
+                    .filter_map(|result| {
+                        let entrant = entrants.iter().find(|e| e.team_name == result.team_name)?;
+                        Some(Outcome {
+                            key_id: entrant.key_id.clone(),
+                            team_name: result.team_name.clone(),
+                            points: result.points,
+                            kills: result.kills,
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn cancel(&mut self) {
+        for m in self.tournament.rounds.iter_mut().flatten() {
+            if m.status == MatchStatus::Playing {
+                m.status = MatchStatus::Pending;
+                m.ends = None;
+            }
+        }
+
+        self.tournament.status = TournamentStatus::Cancelled;
+    }
+
+    fn finish(&mut self, winner: Option<String>) {
+        self.tournament.status = TournamentStatus::Finished;
+        self.tournament.winner = winner;
+    }
+
+    // Most wins first, then most points, then the better seed
+    fn update_standings(&mut self) {
+        let mut standings: Vec<Standing> = self
+            .tournament
+            .entrants
+            .iter()
+            .map(|name| Standing { team_name: name.clone(), ..Default::default() })
+            .collect();
+
+        for m in self.tournament.rounds.iter().flatten() {
+            // A bye moves its player on, but isn't a match they played or won
+            if m.players.len() < 2 {
+                continue;
+            }
+
+            for (place, result) in m.results.iter().enumerate() {
+                if let Some(standing) =
+                    standings.iter_mut().find(|s| s.team_name == result.team_name)
+                {
+                    standing.played += 1;
+                    standing.points += result.points;
+                    if place == 0 {
+                        standing.wins += 1;
+                    }
+                }
+            }
+        }
+
+        standings.sort_by(|a, b| b.wins.cmp(&a.wins).then(b.points.cmp(&a.points)));
+        self.tournament.standings = standings;
+    }
+}
+
+fn empty_result(team_name: &str) -> MatchResult {
+    MatchResult { team_name: team_name.to_string(), points: 0, kills: 0, deaths: 0 }
+}
+
+fn new_match(id: u32, round: usize, index: usize, players: Vec<String>) -> TournamentMatch {
+    TournamentMatch {
+        room: format!("t{}-r{}-m{}", id, round + 1, index + 1),
+        players,
+        status: MatchStatus::Pending,
+        ends: None,
+        results: vec![],
+    }
+}
+
+// Pairs everybody up once with the circle method, so nobody plays twice in
+// the same round. With an odd number of players, one of them sits out each
+// round.
+fn round_robin(id: u32, team_names: &[String]) -> Vec<Vec<TournamentMatch>> {
+    let mut seats: Vec<Option<&String>> = team_names.iter().map(Some).collect();
+    if seats.len() % 2 == 1 {
+        seats.push(None);
+    }
+
+    let half = seats.len() / 2;
+    (0..seats.len() - 1)
+        .map(|round| {
+            let matches = (0..half)
+                .filter_map(|i| match (seats[i], seats[seats.len() - 1 - i]) {
+                    (Some(a), Some(b)) => Some(vec![a.clone(), b.clone()]),
+                    _ => None,
+                })
+                .enumerate()
+                .map(|(index, players)| new_match(id, round, index, players))
+                .collect();
+
+            // Everybody but the first seat moves one seat over
+            let last = seats.pop().unwrap();
+            seats.insert(1, last);
+
+            matches
+        })
+        .collect()
+}
+
+// Splits the players into matches in seeding order. A player left on their
+// own gets a bye.
+fn elimination_round(
+    id: u32,
+    round: usize,
+    team_names: &[String],
+    match_size: usize,
+) -> Vec<TournamentMatch> {
+    team_names
+        .chunks(match_size)
+        .enumerate()
+        .map(|(index, players)| new_match(id, round, index, players.to_vec()))
+        .collect()
+}
+
+/// Every tournament the server has run, kept in a JSON file so the results
+/// survive restarts.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<TournamentRun>, Error> {
+    if !path.as_ref().exists() {
+        return Ok(vec![]);
+    }
+
+    let tournaments = serde_json::from_reader(File::open(path)?)?;
+    Ok(tournaments)
+}
+
+pub fn save<P: AsRef<Path>>(path: P, tournaments: &[TournamentRun]) -> Result<(), Error> {
+    let tmp_path = path.as_ref().with_extension("tmp");
+    serde_json::to_writer_pretty(File::create(&tmp_path)?, tournaments)?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn names(count: usize) -> Vec<String> {
+        (1..=count).map(|i| format!("team{}", i)).collect()
+    }
+
+    fn start(format: TournamentFormat, count: usize, match_size: usize) -> TournamentRun {
+        let config =
+            TournamentConfig { name: None, format, keys: vec![], match_size, round_secs: 60 };
+        let entrants = names(count)
+            .into_iter()
+            .map(|team_name| Entrant { key_id: format!("{}-key", team_name), team_name })
+            .collect();
+        TournamentRun::new(1, &config, entrants).unwrap()
+    }
+
+    fn result(team_name: &str, points: u32) -> RoomResult {
+        RoomResult { key_id: format!("{}-key", team_name), points, kills: 0, deaths: 0 }
+    }
+
+    #[test]
+    fn round_robin_pairs_everybody_once() {
+        for count in 2..=7 {
+            let team_names = names(count);
+            let rounds = round_robin(1, &team_names);
+            assert_eq!(rounds.len(), if count % 2 == 0 { count - 1 } else { count });
+
+            let mut pairs = HashSet::new();
+            for round in &rounds {
+                assert_eq!(round.len(), count / 2);
+
+                let mut seen = HashSet::new();
+                for m in round {
+                    assert_eq!(m.players.len(), 2);
+                    assert!(m.players.iter().all(|name| seen.insert(name.clone())));
+
+                    let mut pair = m.players.clone();
+                    pair.sort();
+                    assert!(pairs.insert(pair), "{:?} played twice", m.players);
+                }
+            }
+            assert_eq!(pairs.len(), count * (count - 1) / 2);
+        }
+    }
+
+    #[test]
+    fn round_robin_gives_a_different_player_a_rest_each_round() {
+        let team_names = names(5);
+        let resting: HashSet<String> = round_robin(1, &team_names)
+            .iter()
+            .map(|round| {
+                let playing: Vec<&String> = round.iter().flat_map(|m| &m.players).collect();
+                team_names.iter().find(|name| !playing.contains(name)).unwrap().clone()
+            })
+            .collect();
+        assert_eq!(resting.len(), 5);
+    }
+
+    #[test]
+    fn elimination_round_gives_the_last_seed_a_bye() {
+        let round = elimination_round(1, 0, &names(5), 2);
+        let players: Vec<Vec<String>> = round.iter().map(|m| m.players.clone()).collect();
+        assert_eq!(
+            players,
+            vec![
+                vec!["team1".to_string(), "team2".to_string()],
+                vec!["team3".to_string(), "team4".to_string()],
+                vec!["team5".to_string()],
+            ]
+        );
+        assert_eq!(round[2].room, "t1-r1-m3");
+
+        let round = elimination_round(1, 0, &names(7), 3);
+        let sizes: Vec<usize> = round.iter().map(|m| m.players.len()).collect();
+        assert_eq!(sizes, vec![3, 3, 1]);
+    }
+
+    #[test]
+    fn byes_finish_right_away() {
+        let mut run = start(TournamentFormat::Elimination, 3, 2);
+        let rooms = run.start_round(SystemTime::now());
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].1, vec!["team1-key".to_string(), "team2-key".to_string()]);
+
+        let bye = &run.tournament.rounds[0][1];
+        assert_eq!(bye.status, MatchStatus::Finished);
+        assert_eq!(bye.results[0].team_name, "team3");
+    }
+
+    #[test]
+    fn elimination_byes_advance_without_a_win() {
+        let mut run = start(TournamentFormat::Elimination, 3, 2);
+        run.start_round(SystemTime::now());
+        assert!(run.finish_round(HashMap::new()));
+
+        let next: Vec<&String> = run.tournament.rounds[1].iter().flat_map(|m| &m.players).collect();
+        assert_eq!(next, vec!["team1", "team3"]);
+
+        let team3 = run.tournament.standings.iter().find(|s| s.team_name == "team3").unwrap();
+        assert_eq!((team3.played, team3.wins), (0, 0));
+    }
+
+    #[test]
+    fn ties_go_to_the_better_seed() {
+        let mut run = start(TournamentFormat::Elimination, 4, 4);
+        run.start_round(SystemTime::now());
+
+        // Nobody showed up, so everybody scored nothing
+        assert!(!run.finish_round(HashMap::new()));
+        let order: Vec<&str> =
+            run.tournament.rounds[0][0].results.iter().map(|r| r.team_name.as_str()).collect();
+        assert_eq!(order, vec!["team1", "team2", "team3", "team4"]);
+
+        let standings: Vec<&str> =
+            run.tournament.standings.iter().map(|s| s.team_name.as_str()).collect();
+        assert_eq!(standings, vec!["team1", "team2", "team3", "team4"]);
+        assert_eq!(run.tournament.winner, Some("team1".to_string()));
+    }
+
+    #[test]
+    fn elimination_plays_down_to_a_single_winner() {
+        let mut run = start(TournamentFormat::Elimination, 5, 2);
+
+        let mut rounds = 0;
+        loop {
+            rounds += 1;
+            let rooms = run.start_round(SystemTime::now());
+
+            // The last seed in each room wins
+            let results = rooms
+                .into_iter()
+                .map(|(room, key_ids)| {
+                    let results = key_ids
+                        .iter()
+                        .enumerate()
+                        .map(|(i, key_id)| result(key_id.trim_end_matches("-key"), i as u32))
+                        .collect();
+                    (room, results)
+                })
+                .collect();
+
+            if !run.finish_round(results) {
+                break;
+            }
+        }
+
+        // 5 -> 3 -> 2 -> 1
+        assert_eq!(rounds, 3);
+        assert_eq!(run.tournament.status, TournamentStatus::Finished);
+        assert_eq!(run.tournament.winner, Some("team5".to_string()));
+        assert!(!run.is_running());
+    }
+
+    #[test]
+    fn standings_count_wins_then_points() {
+        let mut run = start(TournamentFormat::RoundRobin, 3, 2);
+
+        while run.is_running() {
+            let rooms = run.start_round(SystemTime::now());
+            let results = rooms
+                .into_iter()
+                .map(|(room, key_ids)| {
+                    // team3 always wins, team2 scores more than team1
+                    let results = key_ids
+                        .iter()
+                        .map(|key_id| {
+                            let team_name = key_id.trim_end_matches("-key");
+                            let points = match team_name {
+                                "team3" => 10,
+                                "team2" => 5,
+                                _ => 1,
+                            };
+                            result(team_name, points)
+                        })
+                        .collect();
+                    (room, results)
+                })
+                .collect();
+            run.finish_round(results);
+        }
+
+        let standings = &run.tournament.standings;
+        let order: Vec<&str> = standings.iter().map(|s| s.team_name.as_str()).collect();
+        assert_eq!(order, vec!["team3", "team2", "team1"]);
+        assert_eq!(standings.iter().map(|s| s.wins).collect::<Vec<_>>(), vec![2, 1, 0]);
+        assert_eq!(standings.iter().map(|s| s.played).collect::<Vec<_>>(), vec![2, 2, 2]);
+        assert_eq!(standings[0].points, 20);
+        assert_eq!(run.tournament.winner, Some("team3".to_string()));
+    }
+
+    #[test]
+    fn round_robin_byes_do_not_count_as_wins() {
+        let mut run = start(TournamentFormat::RoundRobin, 3, 2);
+        run.start_round(SystemTime::now());
+        run.finish_round(HashMap::new());
+
+        // The player sitting out has no match at all this round
+        let played: u32 = run.tournament.standings.iter().map(|s| s.played).sum();
+        assert_eq!(played, 2);
+    }
+}